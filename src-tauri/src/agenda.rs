@@ -0,0 +1,156 @@
+use crate::card::Card;
+use crate::due;
+use crate::error::CommandError;
+use chrono::{Datelike, Duration, NaiveDate};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Cards with a due date across every board, grouped by how soon they are due.
+// "This week" runs from tomorrow to Sunday.
+#[derive(Serialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Agenda {
+    pub overdue: Vec<AgendaItem>,
+    pub today: Vec<AgendaItem>,
+    pub this_week: Vec<AgendaItem>,
+    pub later: Vec<AgendaItem>,
+    // Cards left out as they could not be read
+    pub warnings: Vec<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AgendaItem {
+    pub board: String,
+    pub lane: String,
+    pub path: String,
+    #[serde(flatten)]
+    pub card: Card,
+}
+
+// `done_lanes` is the content of `done-lanes.json`, the lanes of each board
+// whose cards are finished and left out, e.g. `{ "/project": ["Done"] }`
+pub fn collect(tasks_root: &Path, today: NaiveDate, done_lanes: Option<&Map<String, Value>>) -> Result<Agenda, CommandError> {
+    let mut items = Vec::new();
+    let mut agenda = Agenda::default();
+    let mut visited = HashSet::new();
+    collect_dir(tasks_root, "", today, &mut items, &mut agenda.warnings, &mut visited)?;
+
+    let end_of_week = today + Duration::days(6 - today.weekday().num_days_from_monday() as i64);
+
+    items.sort_by(|(_, a), (_, b)| a.card.due.cmp(&b.card.due).then_with(|| a.path.cmp(&b.path)));
+    for (date, item) in items {
+        if done_lanes.is_some_and(|done_lanes| is_done(done_lanes, &item.board, &item.lane)) {
+            continue;
+        }
+        let group = if date < today {
+            &mut agenda.overdue
+        } else if date == today {
+            &mut agenda.today
+        } else if date <= end_of_week {
+            &mut agenda.this_week
+        } else {
+            &mut agenda.later
+        };
+        group.push(item);
+    }
+
+    Ok(agenda)
+}
+
+fn is_done(done_lanes: &Map<String, Value>, board: &str, lane: &str) -> bool {
+    match done_lanes.get(board) {
+        Some(Value::Array(lanes)) => lanes.iter().any(|done| done.as_str().is_some_and(|done| done.eq_ignore_ascii_case(lane))),
+        _ => false,
+    }
+}
+
+// Walks every board below `dir`, whose frontend path is `path`. Cards sit in
+// lanes, so only files at least two levels below the tasks directory count.
+fn collect_dir(dir: &Path, path: &str, today: NaiveDate, items: &mut Vec<(NaiveDate, AgendaItem)>, warnings: &mut Vec<String>, visited: &mut HashSet<PathBuf>) -> Result<(), CommandError> {
+    // Symlinked lanes pointing back up the tree would otherwise never end
+    let canonical = fs::canonicalize(dir).map_err(|e| CommandError::io(e, path))?;
+    if !visited.insert(canonical) {
+        return Ok(());
+    }
+
+    for entry in fs::read_dir(dir).map_err(|e| CommandError::io(e, path))? {
+        let entry = entry.map_err(|e| CommandError::io(e, path))?;
+        let file_name = entry.file_name().to_string_lossy().to_string();
+        let entry_path = format!("{}/{}", path, file_name);
+        if file_name.starts_with('.') {
+            continue;
+        }
+
+        if entry.path().is_dir() {
+            collect_dir(&entry.path(), &entry_path, today, items, warnings, visited)?;
+            continue;
+        }
+        let (board, lane) = match path.rsplit_once('/') {
+            Some(board_and_lane) if file_name.ends_with(".md") => board_and_lane,
+            _ => continue,
+        };
+
+        let card = match Card::read(&entry.path()) {
+            Ok(card) => card,
+            Err(e) => {
+                warnings.push(format!("\"{}\" could not be read: {}", entry_path, e));
+                continue;
+            }
+        };
+        // Unparseable due dates are left out, as they cannot be placed in time
+        if let Some(date) = card.due_date.as_deref().and_then(|due_date| due::parse_date(due_date, today)) {
+            items.push((date, AgendaItem {
+                board: board.to_string(),
+                lane: lane.to_string(),
+                path: entry_path,
+                card,
+            }));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+    use serde_json::json;
+
+    fn paths(items: &[AgendaItem]) -> Vec<&str> {
+        items.iter().map(|item| item.path.as_str()).collect()
+    }
+
+    #[test]
+    fn groups_due_cards_and_skips_unreadable_ones() {
+        let dir = TempDir::new("agenda-groups");
+        dir.write("Board/Todo/late.md", "[due:2026-10-01]");
+        dir.write("Board/Todo/today.md", "---\ndue: today\n---\n");
+        dir.write("Board/Todo/saturday.md", "[due:2026-10-17]");
+        dir.write("Board/Todo/later.md", "[due:2026-11-01]");
+        dir.write("Board/Todo/undated.md", "No due date");
+        dir.write("Board/Todo/invalid.md", "[due:someday]");
+        dir.write("Board/Todo/broken.md", b"\xff\xfe [due:2026-10-01]");
+        dir.write("Board/Done/finished.md", "[due:2026-10-02]");
+        dir.write("root.md", "[due:2026-10-01]");
+        // A Wednesday
+        let today = NaiveDate::from_ymd_opt(2026, 10, 14).unwrap();
+
+        let agenda = collect(dir.path(), today, None).unwrap();
+        assert_eq!(paths(&agenda.overdue), vec!["/Board/Todo/late.md", "/Board/Done/finished.md"]);
+        assert_eq!(paths(&agenda.today), vec!["/Board/Todo/today.md"]);
+        assert_eq!(paths(&agenda.this_week), vec!["/Board/Todo/saturday.md"]);
+        assert_eq!(paths(&agenda.later), vec!["/Board/Todo/later.md"]);
+        assert_eq!((agenda.overdue[0].board.as_str(), agenda.overdue[0].lane.as_str()), ("/Board", "Todo"));
+        assert_eq!(agenda.warnings.len(), 1);
+        assert!(agenda.warnings[0].starts_with("\"/Board/Todo/broken.md\" could not be read"));
+
+        let done_lanes = json!({ "/Board": ["done"] }).as_object().unwrap().clone();
+        let agenda = collect(dir.path(), today, Some(&done_lanes)).unwrap();
+        assert_eq!(paths(&agenda.overdue), vec!["/Board/Todo/late.md"]);
+    }
+}
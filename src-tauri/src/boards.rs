@@ -0,0 +1,152 @@
+use crate::card::Card;
+use crate::due;
+use crate::error::CommandError;
+use chrono::NaiveDate;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+// A board and the boards nested in its lanes. Every directory is a board
+// whose sub-directories are its lanes, so a lane containing directories is
+// also listed as a board of its own, as when it is opened by its path.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BoardSummary {
+    pub path: String,
+    pub name: String,
+    pub lanes: Vec<LaneSummary>,
+    // Totals of the board's own lanes, nested boards are not included
+    pub card_count: usize,
+    pub overdue_count: usize,
+    // Latest modification of a card in the board's lanes
+    pub last_updated: Option<SystemTime>,
+    pub boards: Vec<BoardSummary>,
+    // Cards of the board's lanes left out of the counts as they could not be read
+    pub warnings: Vec<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LaneSummary {
+    pub name: String,
+    pub card_count: usize,
+    pub overdue_count: usize,
+    pub last_updated: Option<SystemTime>,
+}
+
+// Summarizes the board at `dir`, whose frontend path is `path`, and every
+// board below it
+pub fn summarize(dir: &Path, path: &str, today: NaiveDate) -> Result<BoardSummary, CommandError> {
+    let mut visited = HashSet::new();
+    summarize_board(dir, path, today, &mut visited)
+}
+
+fn summarize_board(dir: &Path, path: &str, today: NaiveDate, visited: &mut HashSet<PathBuf>) -> Result<BoardSummary, CommandError> {
+    // Symlinked lanes pointing back up the tree would otherwise never end
+    if let Ok(canonical) = fs::canonicalize(dir) {
+        visited.insert(canonical);
+    }
+
+    let mut board = BoardSummary {
+        path: path.to_string(),
+        name: path.rsplit('/').next().unwrap_or_default().to_string(),
+        lanes: Vec::new(),
+        card_count: 0,
+        overdue_count: 0,
+        last_updated: None,
+        boards: Vec::new(),
+        warnings: Vec::new(),
+    };
+
+    for lane_dir in subdirectories(dir, path)? {
+        let lane_name = lane_dir.file_name().unwrap_or_default().to_string_lossy().to_string();
+        let lane_path = format!("{}/{}", path, lane_name);
+        let lane = summarize_lane(&lane_dir, &lane_path, lane_name, today, &mut board.warnings)?;
+
+        board.card_count += lane.card_count;
+        board.overdue_count += lane.overdue_count;
+        board.last_updated = board.last_updated.max(lane.last_updated);
+        board.lanes.push(lane);
+
+        let is_new = fs::canonicalize(&lane_dir).is_ok_and(|canonical| !visited.contains(&canonical));
+        if is_new && !subdirectories(&lane_dir, &lane_path)?.is_empty() {
+            board.boards.push(summarize_board(&lane_dir, &lane_path, today, visited)?);
+        }
+    }
+
+    Ok(board)
+}
+
+fn summarize_lane(dir: &Path, path: &str, name: String, today: NaiveDate, warnings: &mut Vec<String>) -> Result<LaneSummary, CommandError> {
+    let mut lane = LaneSummary { name, card_count: 0, overdue_count: 0, last_updated: None };
+
+    for entry in fs::read_dir(dir).map_err(|e| CommandError::io(e, path))? {
+        let entry = entry.map_err(|e| CommandError::io(e, path))?;
+        let file_name = entry.file_name().to_string_lossy().to_string();
+        if !file_name.ends_with(".md") || file_name.starts_with('.') || !entry.path().is_file() {
+            continue;
+        }
+
+        let card = match Card::read(&entry.path()) {
+            Ok(card) => card,
+            Err(e) => {
+                warnings.push(format!("\"{}/{}\" could not be read: {}", path, file_name, e));
+                continue;
+            }
+        };
+        lane.card_count += 1;
+        if card.due_date.as_deref().is_some_and(|due_date| due::is_overdue(due_date, today)) {
+            lane.overdue_count += 1;
+        }
+        lane.last_updated = lane.last_updated.max(Some(card.last_updated));
+    }
+
+    Ok(lane)
+}
+
+// Visible sub-directories of `dir`, sorted by name
+fn subdirectories(dir: &Path, path: &str) -> Result<Vec<PathBuf>, CommandError> {
+    let mut directories = Vec::new();
+    for entry in fs::read_dir(dir).map_err(|e| CommandError::io(e, path))? {
+        let entry = entry.map_err(|e| CommandError::io(e, path))?;
+        if entry.path().is_dir() && !entry.file_name().to_string_lossy().starts_with('.') {
+            directories.push(entry.path());
+        }
+    }
+    directories.sort();
+    Ok(directories)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    #[test]
+    fn summarizes_boards_and_skips_unreadable_cards() {
+        let dir = TempDir::new("boards-summary");
+        dir.write("Todo/late.md", "[due:2026-10-01]");
+        dir.write("Todo/soon.md", "[due:2026-10-20]");
+        dir.write("Todo/broken.md", b"\xff\xfe not UTF-8");
+        dir.write("Todo/notes.txt", "not a card");
+        dir.write("Todo/.hidden.md", "not a card either");
+        dir.write("Project/Doing/task.md", "[due:yesterday]");
+        dir.write(".trash/id/card.md", "trashed");
+        let today = NaiveDate::from_ymd_opt(2026, 10, 14).unwrap();
+
+        let root = summarize(dir.path(), "", today).unwrap();
+        let lanes: Vec<(&str, usize, usize)> = root.lanes.iter().map(|lane| (lane.name.as_str(), lane.card_count, lane.overdue_count)).collect();
+        assert_eq!(lanes, vec![("Project", 0, 0), ("Todo", 2, 1)]);
+        assert_eq!((root.card_count, root.overdue_count), (2, 1));
+        assert_eq!(root.warnings.len(), 1);
+        assert!(root.warnings[0].starts_with("\"/Todo/broken.md\" could not be read"));
+
+        assert_eq!(root.boards.len(), 1);
+        let project = &root.boards[0];
+        assert_eq!((project.path.as_str(), project.name.as_str()), ("/Project", "Project"));
+        assert_eq!((project.card_count, project.overdue_count), (1, 1));
+        assert!(project.warnings.is_empty());
+    }
+}
@@ -0,0 +1,161 @@
+use crate::due;
+use crate::frontmatter;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io;
+use std::ops::Range;
+use std::path::Path;
+use std::time::SystemTime;
+
+// A card as returned to the frontend, with the inline tokens of its content
+// already parsed so consumers don't have to scan the markdown themselves
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Card {
+    pub name: String,
+    pub version: String,
+    pub content: String,
+    // Frontmatter `tags` followed by `[tag:name]` tokens, without duplicates
+    pub tags: Vec<String>,
+    // Frontmatter `due`, or else the value of the first `[due:date]` token, as written
+    pub due_date: Option<String>,
+    // `due_date` resolved to a date, see `due::DueDate` for the accepted forms
+    pub due: Option<String>,
+    pub priority: Option<String>,
+    pub assignee: Option<String>,
+    pub status: Option<String>,
+    // Every frontmatter field, including the ones above and custom ones
+    pub frontmatter: Map<String, Value>,
+    // Every other `[key:value]` token of the body, in order of appearance
+    pub tokens: Vec<Token>,
+    pub last_updated: SystemTime,
+    pub created_at: SystemTime,
+    // Problems worth showing next to the card, such as a due date that is not a date
+    pub warnings: Vec<String>,
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct Token {
+    pub key: String,
+    pub value: String,
+}
+
+impl Card {
+    pub fn read(file_path: &Path) -> io::Result<Card> {
+        let content = fs::read_to_string(file_path)?;
+        let metadata = fs::metadata(file_path)?;
+
+        let file_name = file_path.file_name().unwrap_or_default().to_string_lossy();
+        let name = file_name.strip_suffix(".md").unwrap_or(&file_name).to_string();
+
+        Ok(Card::parse(name, content, &metadata))
+    }
+
+    pub fn parse(name: String, content: String, metadata: &fs::Metadata) -> Card {
+        let (fields, body) = frontmatter::parse(&content);
+
+        let mut tags: Vec<String> = Vec::new();
+        let mut due_date = frontmatter::field_string(&fields, "due");
+        let mut tokens = Vec::new();
+
+        let mut add_tag = |tag: &str| {
+            let tag = tag.trim();
+            if !tag.is_empty() && !tags.iter().any(|existing| existing.eq_ignore_ascii_case(tag)) {
+                tags.push(tag.to_string());
+            }
+        };
+        for tag in frontmatter::field_list(&fields, "tags") {
+            add_tag(&tag);
+        }
+
+        for token in inline_tokens(body) {
+            match token.key.as_str() {
+                "tag" => add_tag(&token.value),
+                "due" => {
+                    if due_date.is_none() {
+                        due_date = Some(token.value);
+                    }
+                }
+                _ => tokens.push(token),
+            }
+        }
+
+        let due = due_date.as_deref().and_then(|due_date| due::parse(due_date, due::today()));
+        let mut warnings = Vec::new();
+        if let (Some(due_date), None) = (&due_date, due) {
+            warnings.push(format!("\"{}\" is not a valid due date", due_date));
+        }
+
+        Card {
+            name,
+            version: version(content.as_bytes()),
+            tags,
+            due: due.map(|due| due.to_string()),
+            due_date,
+            priority: frontmatter::field_string(&fields, "priority"),
+            assignee: frontmatter::field_string(&fields, "assignee"),
+            status: frontmatter::field_string(&fields, "status"),
+            frontmatter: fields,
+            tokens,
+            content,
+            last_updated: metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+            created_at: metadata.created().unwrap_or(SystemTime::UNIX_EPOCH),
+            warnings,
+        }
+    }
+}
+
+// Token sent back by the frontend with `update_resource` to detect edits made
+// on disk since the card was loaded
+pub fn version(content: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(content))
+}
+
+// Finds `[key:value]` tokens. Keys are made of letters, digits, `-` and `_`,
+// values end at the first `]` and never span lines, matching what the
+// frontend used to extract with `/\[tag:(.*?)\]/`. Markdown links such as
+// `[see: docs](url)` are not tokens.
+pub fn inline_tokens(content: &str) -> Vec<Token> {
+    token_ranges(content).into_iter().map(|(_, token)| token).collect()
+}
+
+// Same as `inline_tokens`, along with the byte range of each value in `content`
+pub fn token_ranges(content: &str) -> Vec<(Range<usize>, Token)> {
+    let mut tokens = Vec::new();
+    let mut line_start = 0;
+
+    for line in content.split_inclusive('\n') {
+        let line_end = line_start + line.len();
+        let line = line.trim_end_matches(['\r', '\n']);
+        let mut offset = line_start;
+        let mut rest = line;
+        while let Some(start) = rest.find('[') {
+            rest = &rest[start + 1..];
+            offset += start + 1;
+
+            let key_end = match rest.find(|c: char| !(c.is_ascii_alphanumeric() || c == '-' || c == '_')) {
+                Some(key_end) if key_end > 0 && rest[key_end..].starts_with(':') => key_end,
+                _ => continue,
+            };
+            let value_end = match rest[key_end + 1..].find(']') {
+                Some(value_end) => key_end + 1 + value_end,
+                None => break,
+            };
+            if rest[value_end + 1..].starts_with('(') {
+                continue;
+            }
+
+            tokens.push((offset + key_end + 1..offset + value_end, Token {
+                key: rest[..key_end].to_string(),
+                value: rest[key_end + 1..value_end].to_string(),
+            }));
+            rest = &rest[value_end + 1..];
+            offset += value_end + 1;
+        }
+        line_start = line_end;
+    }
+
+    tokens
+}
@@ -0,0 +1,87 @@
+use crate::storage;
+use fs2::FileExt;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+// Serializes access to the JSON config files shared by every board
+// (`tags.json`, `sort.json`, `views.json`, `done-lanes.json`). Within the app
+// a mutex per file orders the read-modify-write cycles of concurrent commands;
+// across processes, e.g. a second window or instance pointed at the same
+// config dir, an advisory lock on a sibling `.lock` file does the same.
+#[derive(Default)]
+pub struct ConfigStore {
+    locks: Mutex<HashMap<PathBuf, Arc<Mutex<()>>>>,
+}
+
+impl ConfigStore {
+    pub fn read(&self, path: &Path) -> io::Result<Map<String, Value>> {
+        self.with_lock(path, || storage::read_config(path))
+    }
+
+    // Applies `update` to the current content of `path` and writes the result
+    // back, without any other update to the same file in between
+    pub fn update<F>(&self, path: &Path, update: F) -> io::Result<()>
+    where
+        F: FnOnce(&mut Map<String, Value>),
+    {
+        self.with_lock(path, || {
+            let mut config = storage::read_config(path)?;
+            update(&mut config);
+            storage::write_config(path, &config)
+        })
+    }
+
+    // Like `update`, but `update` may fail, in which case the file is left
+    // as it was and the error handed back
+    pub fn try_update<T, E, F>(&self, path: &Path, update: F) -> io::Result<Result<T, E>>
+    where
+        F: FnOnce(&mut Map<String, Value>) -> Result<T, E>,
+    {
+        self.with_lock(path, || {
+            let mut config = storage::read_config(path)?;
+            let result = update(&mut config);
+            if result.is_ok() {
+                storage::write_config(path, &config)?;
+            }
+            Ok(result)
+        })
+    }
+
+    fn with_lock<T, F>(&self, path: &Path, f: F) -> io::Result<T>
+    where
+        F: FnOnce() -> io::Result<T>,
+    {
+        let lock = self.locks.lock().unwrap()
+            .entry(path.to_path_buf())
+            .or_default()
+            .clone();
+        let _guard = lock.lock().unwrap();
+
+        // Called through the trait, as newer std versions have inherent `File`
+        // methods with the same names
+        let file_lock = open_lock_file(path)?;
+        FileExt::lock_exclusive(&file_lock)?;
+        let result = f();
+        let _ = FileExt::unlock(&file_lock);
+
+        result
+    }
+}
+
+// The config files themselves are replaced on every write, so the advisory
+// lock lives on a separate file that is never renamed
+fn open_lock_file(path: &Path) -> io::Result<File> {
+    let dir = path.parent().unwrap_or_else(|| Path::new("."));
+    fs::create_dir_all(dir)?;
+
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(dir.join(format!(".{}.lock", file_name)))
+}
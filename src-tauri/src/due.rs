@@ -0,0 +1,400 @@
+use crate::card;
+use crate::frontmatter;
+use chrono::{Datelike, Duration, FixedOffset, Local, Months, NaiveDate, NaiveTime, Timelike, Weekday};
+use serde_json::Value;
+use std::fmt;
+
+// A due date as written in a card, resolved against the day it is read.
+// Accepted forms, case-insensitive:
+// - dates: `2026-11-01`, `2026-1-5`, `2026/11/01`, `Jan 5`, `5 January 2026`, `January 5, 2026`
+// - relative days: `today`, `tomorrow`, `yesterday`, `+3d`, `-1w`, `+2m`, `+1y`, `in 3 days`
+// - weekdays: `friday` and `this friday` (today on a Friday), `next friday` (after today),
+//   `next week`, `next month`, `next year`
+// - any of the above followed by a time, `10:00`, `10:30:15`, `9am` or `5:30pm`, and an
+//   optional `Z`, `UTC` or `+02:00` offset, also written ISO 8601 style as `2026-11-01T10:00Z`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DueDate {
+    pub date: NaiveDate,
+    pub time: Option<NaiveTime>,
+    pub offset: Option<FixedOffset>,
+}
+
+// Formats as `2026-11-01`, `2026-11-01T10:00` or `2026-11-01T10:00+02:00`.
+// The offset is kept as written rather than converted, so only values
+// without one, or with the same one, sort as text in date order.
+impl fmt::Display for DueDate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.date.format("%Y-%m-%d"))?;
+        if let Some(time) = self.time {
+            if time.second() == 0 {
+                write!(f, "T{}", time.format("%H:%M"))?;
+            } else {
+                write!(f, "T{}", time.format("%H:%M:%S"))?;
+            }
+            if let Some(offset) = self.offset {
+                write!(f, "{}", offset)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn parse(value: &str, today: NaiveDate) -> Option<DueDate> {
+    let value = value.trim().to_lowercase();
+
+    // ISO 8601 writes the date and time together
+    if let Some((date, time)) = value.split_once('t') {
+        if let Some(date) = parse_numeric_date(date) {
+            let (time, offset) = parse_time(time)?;
+            return Some(DueDate { date, time: Some(time), offset });
+        }
+    }
+
+    let words: Vec<&str> = value.split(|c: char| c.is_whitespace() || c == ',').filter(|word| !word.is_empty()).collect();
+    let (words, offset) = match words.split_last() {
+        Some((last, rest)) if !rest.is_empty() => match parse_offset(last) {
+            Some(offset) if rest.last().is_some_and(|word| parse_time(word).is_some()) => (rest, Some(offset)),
+            _ => (&words[..], None),
+        },
+        _ => (&words[..], None),
+    };
+    let (words, time) = match words.split_last() {
+        Some((last, rest)) if !rest.is_empty() => match parse_time(last) {
+            Some((time, time_offset)) => (rest, Some((time, time_offset.or(offset)))),
+            None => (words, None),
+        },
+        _ => (words, None),
+    };
+
+    let date = parse_day(words, today)?;
+    Some(match time {
+        Some((time, offset)) => DueDate { date, time: Some(time), offset },
+        None => DueDate { date, time: None, offset: None },
+    })
+}
+
+pub fn parse_date(value: &str, today: NaiveDate) -> Option<NaiveDate> {
+    parse(value, today).map(|due| due.date)
+}
+
+pub fn today() -> NaiveDate {
+    Local::now().date_naive()
+}
+
+pub fn is_overdue(due_date: &str, today: NaiveDate) -> bool {
+    parse_date(due_date, today).is_some_and(|date| date < today)
+}
+
+// Rewrites the frontmatter `due` field and every `[due:...]` token of a card
+// in the normalized form, so relative dates such as "tomorrow" keep pointing
+// at the day they were written. Values that cannot be parsed are kept as is.
+pub fn normalize_content(content: &str, today: NaiveDate) -> String {
+    let mut normalized = String::with_capacity(content.len());
+    let mut position = 0;
+    for (range, token) in card::token_ranges(content) {
+        if token.key != "due" {
+            continue;
+        }
+        if let Some(due) = parse(&token.value, today) {
+            normalized.push_str(&content[position..range.start]);
+            normalized.push_str(&due.to_string());
+            position = range.end;
+        }
+    }
+    normalized.push_str(&content[position..]);
+
+    let (fields, _) = frontmatter::parse(&normalized);
+    let due = frontmatter::field_string(&fields, "due").and_then(|due| parse(&due, today));
+    match due {
+        Some(due) => frontmatter::set_field(&normalized, "due", Some(&Value::String(due.to_string()))).unwrap_or(normalized),
+        None => normalized,
+    }
+}
+
+fn parse_day(words: &[&str], today: NaiveDate) -> Option<NaiveDate> {
+    match words {
+        ["today"] | ["now"] => Some(today),
+        ["tomorrow"] => today.succ_opt(),
+        ["yesterday"] => today.pred_opt(),
+        ["next", "week"] => Some(today + Duration::weeks(1)),
+        ["next", "month"] => today.checked_add_months(Months::new(1)),
+        ["next", "year"] => today.checked_add_months(Months::new(12)),
+        ["next", weekday] => Some(next_weekday(today + Duration::days(1), parse_weekday(weekday)?)),
+        ["this", weekday] | [weekday] if parse_weekday(weekday).is_some() => Some(next_weekday(today, parse_weekday(weekday)?)),
+        ["in", amount, unit] => add(today, amount.parse().ok()?, unit),
+        [relative] if relative.starts_with(['+', '-']) => {
+            let split = relative.find(|c: char| c.is_alphabetic())?;
+            add(today, relative[..split].parse().ok()?, &relative[split..])
+        }
+        [numeric] => parse_numeric_date(numeric),
+        _ => parse_named_date(words, today),
+    }
+}
+
+// First `weekday` on or after `from`
+fn next_weekday(from: NaiveDate, weekday: Weekday) -> NaiveDate {
+    let days = (7 + weekday.num_days_from_monday() as i64 - from.weekday().num_days_from_monday() as i64) % 7;
+    from + Duration::days(days)
+}
+
+fn add(today: NaiveDate, amount: i64, unit: &str) -> Option<NaiveDate> {
+    let months = |count: i64| {
+        let count = u32::try_from(count.unsigned_abs()).ok()?;
+        if amount < 0 {
+            today.checked_sub_months(Months::new(count))
+        } else {
+            today.checked_add_months(Months::new(count))
+        }
+    };
+    match unit {
+        "d" | "day" | "days" => today.checked_add_signed(Duration::try_days(amount)?),
+        "w" | "week" | "weeks" => today.checked_add_signed(Duration::try_weeks(amount)?),
+        "m" | "month" | "months" => months(amount),
+        "y" | "year" | "years" => months(amount.checked_mul(12)?),
+        _ => None,
+    }
+}
+
+// `2026-11-01` or `2026/11/01`, without requiring leading zeros
+fn parse_numeric_date(value: &str) -> Option<NaiveDate> {
+    let separator = if value.contains('/') { '/' } else { '-' };
+    let mut parts = value.split(separator).map(|part| part.parse::<u32>().ok());
+    let (year, month, day) = (parts.next()??, parts.next()??, parts.next()??);
+    if parts.next().is_some() || year < 1000 {
+        return None;
+    }
+    NaiveDate::from_ymd_opt(year as i32, month, day)
+}
+
+// A month name with a day and an optional year in either order, e.g.
+// `jan 5`, `5 january 2026`. Without a year the current one is used.
+fn parse_named_date(words: &[&str], today: NaiveDate) -> Option<NaiveDate> {
+    let (mut month, mut day, mut year) = (None, None, None);
+    for word in words {
+        if let Some(number) = parse_month(word) {
+            if month.replace(number).is_some() {
+                return None;
+            }
+        } else {
+            let digits = word.trim_end_matches(|c: char| c.is_alphabetic());
+            let suffix = &word[digits.len()..];
+            let number: u32 = digits.parse().ok()?;
+            if !suffix.is_empty() && !matches!(suffix, "st" | "nd" | "rd" | "th") {
+                return None;
+            }
+            match (digits.len(), day, year) {
+                (4, _, None) if suffix.is_empty() => year = Some(number as i32),
+                (1..=2, None, _) => day = Some(number),
+                _ => return None,
+            }
+        }
+    }
+    NaiveDate::from_ymd_opt(year.unwrap_or_else(|| today.year()), month?, day?)
+}
+
+fn parse_month(word: &str) -> Option<u32> {
+    const MONTHS: [&str; 12] = ["january", "february", "march", "april", "may", "june", "july", "august", "september", "october", "november", "december"];
+    let word = word.trim_end_matches('.');
+    if word.len() < 3 {
+        return None;
+    }
+    MONTHS.iter().position(|month| month.starts_with(word)).map(|index| index as u32 + 1)
+}
+
+fn parse_weekday(word: &str) -> Option<Weekday> {
+    const WEEKDAYS: [(&str, Weekday); 7] = [
+        ("monday", Weekday::Mon),
+        ("tuesday", Weekday::Tue),
+        ("wednesday", Weekday::Wed),
+        ("thursday", Weekday::Thu),
+        ("friday", Weekday::Fri),
+        ("saturday", Weekday::Sat),
+        ("sunday", Weekday::Sun),
+    ];
+    if word.len() < 3 {
+        return None;
+    }
+    WEEKDAYS.iter().find(|(name, _)| name.starts_with(word)).map(|(_, weekday)| *weekday)
+}
+
+// `10:00`, `10:00:30`, `9am` or `5:30pm`, optionally followed by an offset
+fn parse_time(value: &str) -> Option<(NaiveTime, Option<FixedOffset>)> {
+    let split = value.find(['z', '+', '-']).unwrap_or(value.len());
+    let (time, offset) = value.split_at(split);
+    let offset = if offset.is_empty() { None } else { Some(parse_offset(offset)?) };
+
+    let (time, meridiem) = match time.strip_suffix("am").or_else(|| time.strip_suffix("pm")) {
+        Some(stripped) => (stripped, Some(time.ends_with("pm"))),
+        None => (time, None),
+    };
+    let mut parts = time.split(':').map(|part| part.parse::<u32>().ok());
+    let mut hour = parts.next()??;
+    let minute = parts.next().unwrap_or(Some(0))?;
+    let second = parts.next().unwrap_or(Some(0))?;
+    if parts.next().is_some() || (meridiem.is_none() && !time.contains(':')) {
+        return None;
+    }
+    if let Some(pm) = meridiem {
+        if !(1..=12).contains(&hour) {
+            return None;
+        }
+        hour = hour % 12 + if pm { 12 } else { 0 };
+    }
+
+    Some((NaiveTime::from_hms_opt(hour, minute, second)?, offset))
+}
+
+// `z`, `utc`, `+02:00`, `+0200` or `+02`
+fn parse_offset(value: &str) -> Option<FixedOffset> {
+    if value == "z" || value == "utc" || value == "gmt" {
+        return FixedOffset::east_opt(0);
+    }
+    let sign = match value.chars().next()? {
+        '+' => 1,
+        '-' => -1,
+        _ => return None,
+    };
+    let digits: String = value[1..].chars().filter(|c| *c != ':').collect();
+    if !digits.chars().all(|c| c.is_ascii_digit()) || !matches!(digits.len(), 2 | 4) {
+        return None;
+    }
+    let hours: i32 = digits[..2].parse().ok()?;
+    let minutes: i32 = digits[2..].parse().unwrap_or(0);
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A Friday
+    fn today() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2026, 10, 16).unwrap()
+    }
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    fn parsed(value: &str) -> Option<String> {
+        parse(value, today()).map(|due| due.to_string())
+    }
+
+    #[test]
+    fn parses_absolute_dates() {
+        assert_eq!(parsed("2026-11-01").as_deref(), Some("2026-11-01"));
+        assert_eq!(parsed("2026-1-5").as_deref(), Some("2026-01-05"));
+        assert_eq!(parsed("2026/11/01").as_deref(), Some("2026-11-01"));
+        assert_eq!(parsed(" 2026-11-01 ").as_deref(), Some("2026-11-01"));
+    }
+
+    #[test]
+    fn parses_named_dates() {
+        assert_eq!(parsed("Jan 5").as_deref(), Some("2026-01-05"));
+        assert_eq!(parsed("5 January 2027").as_deref(), Some("2027-01-05"));
+        assert_eq!(parsed("January 5, 2027").as_deref(), Some("2027-01-05"));
+        assert_eq!(parsed("3rd mar.").as_deref(), Some("2026-03-03"));
+        assert_eq!(parsed("DEC 31").as_deref(), Some("2026-12-31"));
+    }
+
+    #[test]
+    fn parses_relative_days() {
+        assert_eq!(parsed("today").as_deref(), Some("2026-10-16"));
+        assert_eq!(parsed("Tomorrow").as_deref(), Some("2026-10-17"));
+        assert_eq!(parsed("yesterday").as_deref(), Some("2026-10-15"));
+        assert_eq!(parsed("+3d").as_deref(), Some("2026-10-19"));
+        assert_eq!(parsed("-1w").as_deref(), Some("2026-10-09"));
+        assert_eq!(parsed("+2m").as_deref(), Some("2026-12-16"));
+        assert_eq!(parsed("+1y").as_deref(), Some("2027-10-16"));
+        assert_eq!(parsed("in 3 days").as_deref(), Some("2026-10-19"));
+        assert_eq!(parsed("in 2 weeks").as_deref(), Some("2026-10-30"));
+        assert_eq!(parsed("in 1 month").as_deref(), Some("2026-11-16"));
+    }
+
+    #[test]
+    fn parses_weekdays() {
+        assert_eq!(parsed("friday").as_deref(), Some("2026-10-16"));
+        assert_eq!(parsed("this fri").as_deref(), Some("2026-10-16"));
+        assert_eq!(parsed("next friday").as_deref(), Some("2026-10-23"));
+        assert_eq!(parsed("monday").as_deref(), Some("2026-10-19"));
+        assert_eq!(parsed("next thursday").as_deref(), Some("2026-10-22"));
+        assert_eq!(parsed("next week").as_deref(), Some("2026-10-23"));
+        assert_eq!(parsed("next month").as_deref(), Some("2026-11-16"));
+        assert_eq!(parsed("next year").as_deref(), Some("2027-10-16"));
+    }
+
+    #[test]
+    fn parses_times_and_offsets() {
+        assert_eq!(parsed("tomorrow 9am").as_deref(), Some("2026-10-17T09:00"));
+        assert_eq!(parsed("friday 5:30pm").as_deref(), Some("2026-10-16T17:30"));
+        assert_eq!(parsed("12am today").as_deref(), None);
+        assert_eq!(parsed("today 12am").as_deref(), Some("2026-10-16T00:00"));
+        assert_eq!(parsed("2026-11-01 10:30:15").as_deref(), Some("2026-11-01T10:30:15"));
+        assert_eq!(parsed("2026-11-01T10:00Z").as_deref(), Some("2026-11-01T10:00+00:00"));
+        assert_eq!(parsed("2026-11-01 10:00 +0200").as_deref(), Some("2026-11-01T10:00+02:00"));
+        assert_eq!(parsed("next monday 08:15 utc").as_deref(), Some("2026-10-19T08:15+00:00"));
+    }
+
+    #[test]
+    fn rejects_invalid_dates() {
+        for value in ["", "soon", "2026-02-30", "2026-13-01", "26-11-01", "1/2/3", "next blursday", "+3x", "in 3 fortnights", "today 13pm", "today 25:00", "2026-11-01T10:00+2"] {
+            assert_eq!(parsed(value), None, "{}", value);
+        }
+    }
+
+    #[test]
+    fn adds_amounts() {
+        assert_eq!(add(today(), 0, "d"), Some(today()));
+        assert_eq!(add(today(), -16, "days"), Some(date(2026, 9, 30)));
+        assert_eq!(add(today(), 3, "weeks"), Some(date(2026, 11, 6)));
+        assert_eq!(add(today(), -10, "m"), Some(date(2025, 12, 16)));
+        assert_eq!(add(today(), -2, "years"), Some(date(2024, 10, 16)));
+        assert_eq!(add(today(), 1, "fortnight"), None);
+    }
+
+    #[test]
+    fn rejects_amounts_out_of_range() {
+        assert_eq!(add(today(), i64::MIN, "m"), None);
+        assert_eq!(add(today(), i64::MAX, "y"), None);
+        assert_eq!(add(today(), i64::MIN, "d"), None);
+        assert_eq!(add(today(), i64::MAX, "w"), None);
+        assert_eq!(parsed("-9223372036854775808m"), None);
+        assert_eq!(parsed("+99999999999y"), None);
+    }
+
+    #[test]
+    fn clamps_to_the_end_of_the_month() {
+        assert_eq!(add(date(2026, 1, 31), 1, "m"), Some(date(2026, 2, 28)));
+        assert_eq!(add(date(2028, 1, 31), 1, "m"), Some(date(2028, 2, 29)));
+        assert_eq!(add(date(2026, 3, 31), -1, "m"), Some(date(2026, 2, 28)));
+        assert_eq!(add(date(2026, 8, 31), 1, "m"), Some(date(2026, 9, 30)));
+        assert_eq!(add(date(2028, 2, 29), 1, "y"), Some(date(2029, 2, 28)));
+        assert_eq!(parse_day(&["next", "month"], date(2026, 1, 31)), Some(date(2026, 2, 28)));
+    }
+
+    #[test]
+    fn tells_overdue_dates() {
+        assert!(is_overdue("yesterday", today()));
+        assert!(is_overdue("2020-01-01", today()));
+        assert!(is_overdue("2026-10-15T23:59", today()));
+        assert!(!is_overdue("today", today()));
+        assert!(!is_overdue("2026-10-16T00:01", today()));
+        assert!(!is_overdue("tomorrow", today()));
+        assert!(!is_overdue("someday", today()));
+    }
+
+    #[test]
+    fn normalizes_due_dates_in_content() {
+        assert_eq!(
+            normalize_content("Call back [due:tomorrow 9am] and [due:someday]", today()),
+            "Call back [due:2026-10-17T09:00] and [due:someday]",
+        );
+        assert_eq!(
+            normalize_content("---\ndue: next friday\ntags: [work]\n---\nBody [due:+1w]", today()),
+            "---\ndue: 2026-10-23\ntags: [work]\n---\nBody [due:2026-10-23]",
+        );
+        assert_eq!(normalize_content("No due date", today()), "No due date");
+        // Already normalized values stay the same whatever the day
+        assert_eq!(normalize_content("[due:2026-10-17T09:00+02:00]", date(2030, 1, 1)), "[due:2026-10-17T09:00+02:00]");
+    }
+}
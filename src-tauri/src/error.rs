@@ -0,0 +1,228 @@
+use crate::images::ImageError;
+use crate::paths::PathError;
+use crate::query::QueryError;
+use serde::Serialize;
+use std::fmt;
+use std::io;
+
+// Stable identifiers the frontend can match on, serialized in camelCase
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ErrorCode {
+    NotFound,
+    AlreadyExists,
+    PermissionDenied,
+    InvalidName,
+    // The path escapes the tasks or config directory, targets its root or
+    // reaches a hidden entry
+    InvalidPath,
+    // The card changed on disk since the frontend loaded it
+    Conflict,
+    // A card or config file could not be parsed
+    InvalidData,
+    // A card query could not be parsed
+    InvalidQuery,
+    // An upload is not an image in a supported format
+    UnsupportedImage,
+    // An upload is over the size limit
+    TooLarge,
+    // The feature backing the command is disabled
+    Unavailable,
+    Io,
+    Other,
+}
+
+// Error returned by every command, serialized as
+// `{ "code": "...", "path": "...", "message": "..." }`. `path` is the
+// offending path in the frontend form when there is one, and `message` is
+// meant to be shown to the user as is.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CommandError {
+    pub code: ErrorCode,
+    pub path: Option<String>,
+    pub message: String,
+    // Only set for `conflict`
+    #[serde(flatten)]
+    pub conflict: Option<Box<Conflict>>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Conflict {
+    pub expected_version: String,
+    pub current_version: String,
+    pub current_content: String,
+}
+
+impl CommandError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        CommandError { code, path: None, message: message.into(), conflict: None }
+    }
+
+    pub fn at(mut self, path: &str) -> Self {
+        self.path = Some(path.to_string());
+        self
+    }
+
+    pub fn not_found(path: &str) -> Self {
+        let entry = Entry::parse(path);
+        CommandError::new(ErrorCode::NotFound, format!("{} does not exist{}", capitalize(&entry.label()), entry.location()))
+            .at(path)
+    }
+
+    pub fn already_exists(path: &str) -> Self {
+        let entry = Entry::parse(path);
+        CommandError::new(ErrorCode::AlreadyExists, format!("A {} named \"{}\" already exists{}", entry.kind, entry.name, entry.location()))
+            .at(path)
+    }
+
+    pub fn invalid_name(path: &str, reason: &str) -> Self {
+        let entry = Entry::parse(path);
+        CommandError::new(ErrorCode::InvalidName, format!("\"{}\" is not a valid {} name: {}", entry.name, entry.kind, reason))
+            .at(path)
+    }
+
+    pub fn conflict(path: &str, expected_version: String, current_version: String, current_content: String) -> Self {
+        let entry = Entry::parse(path);
+        CommandError {
+            code: ErrorCode::Conflict,
+            path: Some(path.to_string()),
+            message: format!("{}{} was changed outside the app", capitalize(&entry.label()), entry.location()),
+            conflict: Some(Box::new(Conflict { expected_version, current_version, current_content })),
+        }
+    }
+
+    // Maps a filesystem error on `path` to its code, with a message naming
+    // the card or lane instead of the raw OS error
+    pub fn io(e: io::Error, path: &str) -> Self {
+        let entry = Entry::parse(path);
+        match e.kind() {
+            io::ErrorKind::NotFound => CommandError::not_found(path),
+            io::ErrorKind::AlreadyExists => CommandError::already_exists(path),
+            io::ErrorKind::PermissionDenied => CommandError::new(
+                ErrorCode::PermissionDenied,
+                format!("Permission denied on {}{}", entry.label(), entry.location()),
+            ).at(path),
+            io::ErrorKind::InvalidData => CommandError::new(
+                ErrorCode::InvalidData,
+                format!("{}{} is invalid: {}", capitalize(&entry.label()), entry.location(), e),
+            ).at(path),
+            _ => CommandError::new(
+                ErrorCode::Io,
+                format!("Could not access {}{}: {}", entry.label(), entry.location(), e),
+            ).at(path),
+        }
+    }
+
+    // Like `io`, blaming `to` when it is taken and `from` otherwise
+    pub fn rename(e: io::Error, from: &str, to: &str) -> Self {
+        match e.kind() {
+            io::ErrorKind::AlreadyExists => CommandError::already_exists(to),
+            _ => CommandError::io(e, from),
+        }
+    }
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for CommandError {}
+
+impl From<io::Error> for CommandError {
+    fn from(e: io::Error) -> Self {
+        let code = match e.kind() {
+            io::ErrorKind::NotFound => ErrorCode::NotFound,
+            io::ErrorKind::AlreadyExists => ErrorCode::AlreadyExists,
+            io::ErrorKind::PermissionDenied => ErrorCode::PermissionDenied,
+            io::ErrorKind::InvalidData => ErrorCode::InvalidData,
+            _ => ErrorCode::Io,
+        };
+        CommandError::new(code, e.to_string())
+    }
+}
+
+impl From<PathError> for CommandError {
+    fn from(e: PathError) -> Self {
+        match e {
+            PathError::OutsideWorkspace(path) => {
+                CommandError::new(ErrorCode::InvalidPath, format!("{} is outside the workspace", path)).at(&path)
+            }
+            PathError::WorkspaceRoot(path) => {
+                CommandError::new(ErrorCode::InvalidPath, "The workspace root cannot be renamed or deleted").at(&path)
+            }
+            PathError::InvalidName(path, reason) => CommandError::invalid_name(&path, reason),
+            PathError::Hidden(path) => {
+                CommandError::new(ErrorCode::InvalidPath, format!("{} is not a board, lane or card", path)).at(&path)
+            }
+            PathError::Io(e) => e.into(),
+        }
+    }
+}
+
+impl From<QueryError> for CommandError {
+    fn from(e: QueryError) -> Self {
+        CommandError::new(ErrorCode::InvalidQuery, format!("Invalid query: {}", e))
+    }
+}
+
+impl From<ImageError> for CommandError {
+    fn from(e: ImageError) -> Self {
+        let code = match e {
+            ImageError::Unsupported(_) => ErrorCode::UnsupportedImage,
+            ImageError::TooLarge { .. } => ErrorCode::TooLarge,
+        };
+        CommandError::new(code, e.to_string())
+    }
+}
+
+impl From<git2::Error> for CommandError {
+    fn from(e: git2::Error) -> Self {
+        let code = match e.code() {
+            git2::ErrorCode::NotFound => ErrorCode::NotFound,
+            _ => ErrorCode::Other,
+        };
+        CommandError::new(code, format!("Git history: {}", e.message()))
+    }
+}
+
+// What a frontend path points to, e.g. "/board/Done/Fix login.md" is the
+// card "Fix login" in the lane "Done"
+struct Entry<'a> {
+    kind: &'static str,
+    name: &'a str,
+    lane: Option<&'a str>,
+}
+
+impl<'a> Entry<'a> {
+    fn parse(path: &'a str) -> Self {
+        let mut parts = path.trim_matches('/').rsplit('/');
+        let file_name = parts.next().unwrap_or_default();
+        let parent = parts.next().filter(|parent| !parent.is_empty());
+
+        match file_name.strip_suffix(".md") {
+            Some(name) => Entry { kind: "card", name, lane: parent },
+            None if file_name.contains('.') => Entry { kind: "file", name: file_name, lane: None },
+            None => Entry { kind: "lane", name: file_name, lane: None },
+        }
+    }
+
+    fn label(&self) -> String {
+        format!("{} \"{}\"", self.kind, self.name)
+    }
+
+    fn location(&self) -> String {
+        self.lane.map(|lane| format!(" in {}", lane)).unwrap_or_default()
+    }
+}
+
+fn capitalize(text: &str) -> String {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
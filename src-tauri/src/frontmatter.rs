@@ -0,0 +1,387 @@
+use serde_json::{Map, Value};
+
+// Metadata block at the top of a card, as used by Obsidian (YAML between
+// `---` lines) and Hugo (YAML, or TOML between `+++` lines)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Yaml,
+    Toml,
+}
+
+impl Format {
+    fn delimiter(self) -> &'static str {
+        match self {
+            Format::Yaml => "---",
+            Format::Toml => "+++",
+        }
+    }
+}
+
+// Byte ranges of a frontmatter block inside a card
+struct Block {
+    format: Format,
+    // Text between the opening and closing delimiter lines
+    raw: std::ops::Range<usize>,
+    // Everything after the closing delimiter line
+    body: usize,
+}
+
+// Format of the block opened by the first line, whether it is closed or not
+fn opening(content: &str) -> Option<Format> {
+    let first = content.split_inclusive('\n').next()?.trim_end();
+    [Format::Yaml, Format::Toml].into_iter().find(|format| first == format.delimiter())
+}
+
+fn find_block(content: &str) -> Option<Block> {
+    let format = opening(content)?;
+    let mut lines = split_lines(content);
+    let (_, first) = lines.next()?;
+
+    let raw_start = first.len();
+    for (offset, line) in lines {
+        if line.trim_end() == format.delimiter() {
+            return Some(Block { format, raw: raw_start..offset, body: offset + line.len() });
+        }
+    }
+
+    None
+}
+
+// Lines with their terminators, along with their byte offset
+fn split_lines(text: &str) -> impl Iterator<Item = (usize, &str)> {
+    text.split_inclusive('\n').scan(0, |offset, line| {
+        let start = *offset;
+        *offset += line.len();
+        Some((start, line))
+    })
+}
+
+// Splits a card into its frontmatter fields and body. Cards without
+// frontmatter, or whose frontmatter cannot be parsed, are all body.
+pub fn parse(content: &str) -> (Map<String, Value>, &str) {
+    match find_block(content) {
+        Some(block) => match parse_fields(block.format, &content[block.raw]) {
+            Ok(fields) => (fields, &content[block.body..]),
+            Err(_) => (Map::new(), content),
+        },
+        None => (Map::new(), content),
+    }
+}
+
+fn parse_fields(format: Format, raw: &str) -> Result<Map<String, Value>, String> {
+    match format {
+        Format::Yaml => {
+            if raw.trim().is_empty() {
+                return Ok(Map::new());
+            }
+            match serde_yaml::from_str::<Value>(raw).map_err(|e| e.to_string())? {
+                Value::Object(fields) => Ok(fields),
+                Value::Null => Ok(Map::new()),
+                _ => Err("frontmatter is not a mapping".to_string()),
+            }
+        }
+        Format::Toml => {
+            let table = toml::from_str::<toml::Table>(raw).map_err(|e| e.to_string())?;
+            Ok(table.into_iter().map(|(key, value)| (key, toml_to_json(value))).collect())
+        }
+    }
+}
+
+// TOML dates have no JSON counterpart, so they are kept as strings
+fn toml_to_json(value: toml::Value) -> Value {
+    match value {
+        toml::Value::String(s) => Value::String(s),
+        toml::Value::Integer(i) => Value::from(i),
+        toml::Value::Float(f) => Value::from(f),
+        toml::Value::Boolean(b) => Value::Bool(b),
+        toml::Value::Datetime(datetime) => Value::String(datetime.to_string()),
+        toml::Value::Array(array) => Value::Array(array.into_iter().map(toml_to_json).collect()),
+        toml::Value::Table(table) => Value::Object(table.into_iter().map(|(key, value)| (key, toml_to_json(value))).collect()),
+    }
+}
+
+// Sets `key` in the frontmatter of `content`, or removes it when `value` is
+// `None`, leaving the body untouched. Only the lines of that field are
+// rewritten so comments and the order of the other fields survive. Cards
+// without frontmatter get a YAML block. Frontmatter that cannot be parsed,
+// or is never closed, is an error rather than being replaced.
+pub fn set_field(content: &str, key: &str, value: Option<&Value>) -> Result<String, String> {
+    if !is_valid_key(key) {
+        return Err(format!("invalid frontmatter field: {}", key));
+    }
+
+    let block = match find_block(content) {
+        Some(block) => block,
+        None if opening(content).is_some() => return Err("invalid frontmatter: no closing delimiter".to_string()),
+        None => {
+            return match value {
+                Some(value) => Ok(format!("---\n{}---\n{}", serialize_entry(Format::Yaml, key, value)?, content)),
+                None => Ok(content.to_string()),
+            };
+        }
+    };
+
+    let raw = &content[block.raw.clone()];
+    let mut expected = parse_fields(block.format, raw).map_err(|e| format!("invalid frontmatter: {}", e))?;
+    match value {
+        Some(value) => expected.insert(key.to_string(), value.clone()),
+        None => expected.remove(key),
+    };
+
+    let edited = edit_entry(block.format, raw, key, value)?;
+    let mut new_raw = match parse_fields(block.format, &edited) {
+        Ok(fields) if fields == expected => edited,
+        // The field spans lines in a way the line-based edit cannot follow
+        _ => serialize_fields(block.format, &expected)?,
+    };
+    // Serializers only emit `\n`, keep files written on Windows consistent
+    if raw.contains("\r\n") {
+        new_raw = new_raw.replace("\r\n", "\n").replace('\n', "\r\n");
+    }
+
+    Ok(format!("{}{}{}", &content[..block.raw.start], new_raw, &content[block.raw.end..]))
+}
+
+// Field names are limited to what can be edited line by line without quoting
+pub fn is_valid_key(key: &str) -> bool {
+    !key.is_empty() && key.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+}
+
+fn edit_entry(format: Format, raw: &str, key: &str, value: Option<&Value>) -> Result<String, String> {
+    let lines: Vec<&str> = raw.split_inclusive('\n').collect();
+    let replacement = match value {
+        Some(value) => serialize_entry(format, key, value)?,
+        None => String::new(),
+    };
+
+    let mut edited = String::with_capacity(raw.len() + replacement.len());
+    match find_entry(format, &lines, key) {
+        Some(range) => {
+            edited.extend(lines[..range.start].iter().copied());
+            edited.push_str(&replacement);
+            edited.extend(lines[range.end..].iter().copied());
+        }
+        None => {
+            // New TOML keys must come before the first table
+            let insert_at = match format {
+                Format::Toml => lines.iter().position(|line| line.trim_start().starts_with('[')).unwrap_or(lines.len()),
+                Format::Yaml => lines.len(),
+            };
+            edited.extend(lines[..insert_at].iter().copied());
+            if !edited.is_empty() && !edited.ends_with('\n') {
+                edited.push('\n');
+            }
+            edited.push_str(&replacement);
+            edited.extend(lines[insert_at..].iter().copied());
+        }
+    }
+
+    Ok(edited)
+}
+
+// Lines holding the top-level `key` entry
+fn find_entry(format: Format, lines: &[&str], key: &str) -> Option<std::ops::Range<usize>> {
+    let start = lines.iter().position(|line| {
+        let rest = match line.strip_prefix(key) {
+            Some(rest) => rest,
+            None => return false,
+        };
+        match format {
+            Format::Yaml => rest.starts_with(':'),
+            Format::Toml => rest.trim_start().starts_with('='),
+        }
+    })?;
+
+    if format == Format::Toml && lines[..start].iter().any(|line| line.trim_start().starts_with('[')) {
+        return None;
+    }
+
+    let mut end = start + 1;
+    match format {
+        // Nested values are indented, block sequences may also start at column 0
+        Format::Yaml => {
+            while end < lines.len() && (lines[end].starts_with([' ', '\t', '-']) || lines[end].trim().is_empty()) {
+                end += 1;
+            }
+            while end > start + 1 && lines[end - 1].trim().is_empty() {
+                end -= 1;
+            }
+        }
+        // Multi-line arrays and strings end once the entry parses on its own
+        Format::Toml => {
+            while end < lines.len() && toml::from_str::<toml::Table>(&lines[start..end].concat()).is_err() {
+                end += 1;
+            }
+        }
+    }
+
+    Some(start..end)
+}
+
+fn serialize_entry(format: Format, key: &str, value: &Value) -> Result<String, String> {
+    let mut entry = Map::new();
+    entry.insert(key.to_string(), value.clone());
+    serialize_fields(format, &entry)
+}
+
+fn serialize_fields(format: Format, fields: &Map<String, Value>) -> Result<String, String> {
+    if fields.is_empty() {
+        return Ok(String::new());
+    }
+
+    let serialized = match format {
+        Format::Yaml => serde_yaml::to_string(fields).map_err(|e| e.to_string())?,
+        Format::Toml => toml::to_string(fields).map_err(|e| e.to_string())?,
+    };
+
+    if serialized.ends_with('\n') {
+        Ok(serialized)
+    } else {
+        Ok(serialized + "\n")
+    }
+}
+
+// Reads a scalar field as a string, e.g. `priority: 2` gives "2"
+pub fn field_string(fields: &Map<String, Value>, key: &str) -> Option<String> {
+    match fields.get(key)? {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+// `tags` may be a list or a single, possibly comma separated, string
+pub fn field_list(fields: &Map<String, Value>, key: &str) -> Vec<String> {
+    match fields.get(key) {
+        Some(Value::Array(items)) => items.iter()
+            .filter_map(|item| match item {
+                Value::String(s) => Some(s.clone()),
+                Value::Number(n) => Some(n.to_string()),
+                _ => None,
+            })
+            .collect(),
+        Some(Value::String(s)) => s.split(',').map(|tag| tag.to_string()).collect(),
+        _ => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn set(content: &str, key: &str, value: Option<Value>) -> String {
+        set_field(content, key, value.as_ref()).unwrap()
+    }
+
+    #[test]
+    fn detects_yaml_and_toml() {
+        let (fields, body) = parse("---\ntags: [bug, ui]\npriority: 2\n---\nBody\n");
+        assert_eq!(Value::Object(fields), json!({ "tags": ["bug", "ui"], "priority": 2 }));
+        assert_eq!(body, "Body\n");
+
+        let (fields, body) = parse("+++\ntags = [\"bug\"]\ndue = 2026-11-01\n+++\nBody\n");
+        assert_eq!(Value::Object(fields), json!({ "tags": ["bug"], "due": "2026-11-01" }));
+        assert_eq!(body, "Body\n");
+
+        let (fields, body) = parse("---\r\nstatus: done\r\n---\r\nBody");
+        assert_eq!(field_string(&fields, "status").as_deref(), Some("done"));
+        assert_eq!(body, "Body");
+
+        let (fields, body) = parse("---\n---\nBody");
+        assert!(fields.is_empty());
+        assert_eq!(body, "Body");
+    }
+
+    #[test]
+    fn leaves_other_content_as_body() {
+        for content in ["Body\n", "--- not a block\nBody\n---\n", "---\nunclosed: yes\n", "---\n- a list\n---\n", "+++\nnot toml\n+++\n"] {
+            let (fields, body) = parse(content);
+            assert!(fields.is_empty(), "{}", content);
+            assert_eq!(body, content);
+        }
+    }
+
+    #[test]
+    fn updates_a_single_yaml_field() {
+        let content = "---\n# Owner comment\nassignee: sam\ntags:\n  - bug\npriority: 1\n---\nBody [tag:ui]\n\n---\nMore\n";
+
+        let updated = set(content, "priority", Some(json!(3)));
+        assert_eq!(updated, "---\n# Owner comment\nassignee: sam\ntags:\n  - bug\npriority: 3\n---\nBody [tag:ui]\n\n---\nMore\n");
+
+        let updated = set(content, "tags", Some(json!(["bug", "ui"])));
+        assert_eq!(updated, "---\n# Owner comment\nassignee: sam\ntags:\n- bug\n- ui\npriority: 1\n---\nBody [tag:ui]\n\n---\nMore\n");
+
+        let updated = set(content, "status", Some(json!("done")));
+        assert!(updated.starts_with("---\n# Owner comment\nassignee: sam\ntags:\n  - bug\npriority: 1\nstatus: done\n---\n"));
+
+        let updated = set(content, "assignee", None);
+        assert_eq!(updated, "---\n# Owner comment\ntags:\n  - bug\npriority: 1\n---\nBody [tag:ui]\n\n---\nMore\n");
+    }
+
+    #[test]
+    fn updates_a_single_toml_field() {
+        let content = "+++\ntitle = \"Login\" # kept\ntags = [\n  \"bug\",\n]\n\n[extra]\nowner = \"sam\"\n+++\nBody\n";
+
+        let updated = set(content, "tags", Some(json!(["ui"])));
+        assert_eq!(updated, "+++\ntitle = \"Login\" # kept\ntags = [\"ui\"]\n\n[extra]\nowner = \"sam\"\n+++\nBody\n");
+
+        let updated = set(content, "due", Some(json!("2026-11-01")));
+        assert_eq!(updated, "+++\ntitle = \"Login\" # kept\ntags = [\n  \"bug\",\n]\n\ndue = \"2026-11-01\"\n[extra]\nowner = \"sam\"\n+++\nBody\n");
+
+        let (fields, body) = parse(&updated);
+        assert_eq!(field_string(&fields, "due").as_deref(), Some("2026-11-01"));
+        assert_eq!(fields["extra"], json!({ "owner": "sam" }));
+        assert_eq!(body, "Body\n");
+    }
+
+    #[test]
+    fn round_trips_field_updates() {
+        for content in ["---\na: 1\n---\nBody", "+++\na = 1\n+++\nBody", "---\r\na: 1\r\n---\r\nBody\r\n"] {
+            let updated = set(content, "b", Some(json!("two words")));
+            let (fields, body) = parse(&updated);
+            assert_eq!(Value::Object(fields), json!({ "a": 1, "b": "two words" }), "{}", content);
+            assert_eq!(body, parse(content).1);
+            assert_eq!(set(&updated, "b", None), content);
+        }
+    }
+
+    #[test]
+    fn adds_a_block_to_cards_without_one() {
+        assert_eq!(set("Body\n", "due", Some(json!("2026-11-01"))), "---\ndue: 2026-11-01\n---\nBody\n");
+        assert_eq!(set("Body\n", "due", None), "Body\n");
+    }
+
+    #[test]
+    fn refuses_to_replace_frontmatter_it_cannot_read() {
+        for content in [
+            "---\ntags: [bug\n---\nBody",
+            "---\n- a list\n---\nBody",
+            "+++\nnot toml\n+++\nBody",
+            "---\nunclosed: yes\nBody",
+            "+++\r\nunclosed = true\r\n",
+        ] {
+            assert!(set_field(content, "status", Some(&json!("done"))).is_err(), "{}", content);
+            assert!(set_field(content, "status", None).is_err(), "{}", content);
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_keys() {
+        for key in ["", "a b", "a:b", "a.b", "#a"] {
+            assert!(set_field("Body", key, Some(&json!(1))).is_err(), "{}", key);
+        }
+        assert!(is_valid_key("due_date-2"));
+    }
+
+    #[test]
+    fn reads_lists_and_scalars() {
+        let (fields, _) = parse("---\ntags: bug,ui\nids: [1, two, {}]\ndone: true\nnested: {a: 1}\n---\n");
+        assert_eq!(field_list(&fields, "tags"), ["bug", "ui"]);
+        assert_eq!(field_list(&fields, "ids"), ["1", "two"]);
+        assert_eq!(field_string(&fields, "done").as_deref(), Some("true"));
+        assert_eq!(field_string(&fields, "nested"), None);
+        assert!(field_list(&fields, "missing").is_empty());
+    }
+}
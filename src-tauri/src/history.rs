@@ -0,0 +1,545 @@
+use git2::{Delta, DiffOptions, Index, IndexEntry, IndexTime, Oid, Patch, Repository, Signature, Sort, Tree};
+use serde::Serialize;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::Mutex;
+use std::time::Duration;
+
+// Quiet period after the last mutation before its changes are committed, so
+// a burst of edits (e.g. typing in a card) ends up in a single commit
+const COMMIT_DELAY: Duration = Duration::from_secs(3);
+// Upper bound on commits walked when listing a card's history
+const MAX_HISTORY: usize = 200;
+
+// Optional git history of the tasks directory. When enabled, every mutating
+// command queues a message describing its change and a background thread
+// commits everything under the tasks directory once edits settle.
+#[derive(Default)]
+pub struct History {
+    sender: Option<Mutex<Sender<String>>>,
+}
+
+impl History {
+    // Uses the repository holding `tasks_root`, or initializes one there
+    pub fn start(tasks_root: &Path) -> Result<Self, git2::Error> {
+        open_or_init(tasks_root)?;
+
+        let (sender, receiver) = mpsc::channel();
+        let tasks_root = tasks_root.to_path_buf();
+        std::thread::spawn(move || commit_loop(tasks_root, receiver));
+
+        Ok(History { sender: Some(Mutex::new(sender)) })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.sender.is_some()
+    }
+
+    pub fn record(&self, message: String) {
+        if let Some(sender) = &self.sender {
+            let _ = sender.lock().unwrap().send(message);
+        }
+    }
+}
+
+// The repository holding the tasks directory, found the way git finds it so
+// boards kept in a larger repository are versioned there rather than in a
+// nested one, along with the path of the tasks directory in its working tree
+fn open_or_init(tasks_root: &Path) -> Result<(Repository, PathBuf), git2::Error> {
+    let repo = match Repository::discover(tasks_root) {
+        Ok(repo) => repo,
+        Err(e) if e.code() == git2::ErrorCode::NotFound => Repository::init(tasks_root)?,
+        Err(e) => return Err(e),
+    };
+
+    let workdir = repo.workdir().ok_or_else(|| git2::Error::from_str("repository has no working tree"))?;
+    let workdir = workdir.canonicalize().map_err(io_error)?;
+    let prefix = tasks_root.canonicalize().map_err(io_error)?
+        .strip_prefix(&workdir)
+        .map(Path::to_path_buf)
+        .map_err(|_| git2::Error::from_str("the tasks directory is outside the working tree of its repository"))?;
+    Ok((repo, prefix))
+}
+
+fn commit_loop(tasks_root: PathBuf, receiver: Receiver<String>) {
+    while let Ok(first) = receiver.recv() {
+        let mut messages = vec![first];
+        let mut disconnected = false;
+
+        loop {
+            match receiver.recv_timeout(COMMIT_DELAY) {
+                Ok(message) => messages.push(message),
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => {
+                    disconnected = true;
+                    break;
+                }
+            }
+        }
+
+        if let Err(e) = commit(&tasks_root, &messages) {
+            eprintln!("Failed to commit tasks history: {}", e);
+        }
+        if disconnected {
+            return;
+        }
+    }
+}
+
+// Commits every change under `tasks_root`, skipping hidden entries such as
+// the trash or in-flight temporary files. Nothing is committed when the tree
+// did not change.
+fn commit(tasks_root: &Path, messages: &[String]) -> Result<(), git2::Error> {
+    let (repo, prefix) = open_or_init(tasks_root)?;
+    let parent = match repo.head() {
+        Ok(head) => Some(head.peel_to_commit()?),
+        Err(_) => None,
+    };
+    let parent_tree = parent.as_ref().map(|parent| parent.tree()).transpose()?;
+
+    // The tree is built in an in-memory index starting from HEAD, where only
+    // the visible entries below the tasks directory are replaced. The rest of
+    // the repository and hidden entries committed by hand are kept as they are.
+    let mut index = Index::new()?;
+    if let Some(parent_tree) = &parent_tree {
+        index.read_tree(parent_tree)?;
+    }
+    let replaced: Vec<PathBuf> = index.iter()
+        .map(|entry| PathBuf::from(String::from_utf8_lossy(&entry.path).to_string()))
+        .filter(|path| path.strip_prefix(&prefix).is_ok_and(|relative| !is_hidden(relative)))
+        .collect();
+    for path in replaced {
+        index.remove(&path, 0)?;
+    }
+    add_dir(&repo, &mut index, tasks_root, &prefix)?;
+
+    let tree = repo.find_tree(index.write_tree_to(&repo)?)?;
+    if parent_tree.as_ref().is_some_and(|parent_tree| parent_tree.id() == tree.id()) {
+        return Ok(());
+    }
+
+    let signature = repo.signature().or_else(|_| Signature::now("Tasks.md", "tasks.md@localhost"))?;
+    let parents: Vec<_> = parent.iter().collect();
+    repo.commit(Some("HEAD"), &signature, &signature, &commit_message(messages), &tree, &parents)?;
+
+    sync_index(&repo, parent_tree.as_ref(), &tree)
+}
+
+// Moves the repository's index along with HEAD, so `git status` does not show
+// the commit as staged reversals, keeping what the user staged on top of it
+fn sync_index(repo: &Repository, old_tree: Option<&Tree>, new_tree: &Tree) -> Result<(), git2::Error> {
+    let mut index = repo.index()?;
+    let staged = repo.diff_tree_to_index(old_tree, Some(&index), None)?;
+
+    let mut kept = Vec::new();
+    let mut removed = Vec::new();
+    for delta in staged.deltas() {
+        match (delta.status(), delta.old_file().path(), delta.new_file().path()) {
+            (Delta::Deleted, Some(path), _) => removed.push(path.to_path_buf()),
+            (_, _, Some(path)) => kept.extend(index.get_path(path, 0)),
+            _ => {}
+        }
+    }
+
+    index.read_tree(new_tree)?;
+    for entry in &kept {
+        index.add(entry)?;
+    }
+    for path in &removed {
+        if index.get_path(path, 0).is_some() {
+            index.remove(path, 0)?;
+        }
+    }
+    index.write()
+}
+
+// Adds the visible files below `dir`, at `relative` in the repository, to
+// `index`. Symlinks are stored as links and never followed, as git does.
+fn add_dir(repo: &Repository, index: &mut Index, dir: &Path, relative: &Path) -> Result<(), git2::Error> {
+    for entry in fs::read_dir(dir).map_err(io_error)? {
+        let entry = entry.map_err(io_error)?;
+        if entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        let path = relative.join(entry.file_name());
+        let file_type = entry.file_type().map_err(io_error)?;
+        if file_type.is_dir() {
+            add_dir(repo, index, &entry.path(), &path)?;
+            continue;
+        }
+
+        let read = if file_type.is_symlink() {
+            fs::read_link(entry.path()).map(|target| (0o120000, target.to_string_lossy().replace('\\', "/").into_bytes()))
+        } else {
+            entry.metadata().and_then(|metadata| Ok((file_mode(&metadata), fs::read(entry.path())?)))
+        };
+        let (mode, content) = match read {
+            Ok(read) => read,
+            // Removed since the directory was listed, the next commit has it gone
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(io_error(e)),
+        };
+
+        index.add(&IndexEntry {
+            ctime: IndexTime::new(0, 0),
+            mtime: IndexTime::new(0, 0),
+            dev: 0,
+            ino: 0,
+            mode,
+            uid: 0,
+            gid: 0,
+            file_size: content.len() as u32,
+            id: repo.blob(&content)?,
+            flags: 0,
+            flags_extended: 0,
+            path: to_git_path(&path).into_bytes(),
+        })?;
+    }
+
+    Ok(())
+}
+
+#[cfg(unix)]
+fn file_mode(metadata: &fs::Metadata) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    if metadata.permissions().mode() & 0o111 != 0 { 0o100755 } else { 0o100644 }
+}
+
+#[cfg(not(unix))]
+fn file_mode(_: &fs::Metadata) -> u32 {
+    0o100644
+}
+
+fn io_error(e: io::Error) -> git2::Error {
+    git2::Error::from_str(&e.to_string())
+}
+
+fn commit_message(messages: &[String]) -> String {
+    let mut unique: Vec<&String> = Vec::new();
+    for message in messages {
+        if !unique.contains(&message) {
+            unique.push(message);
+        }
+    }
+
+    match unique.as_slice() {
+        [message] => message.to_string(),
+        _ => {
+            let body: Vec<String> = unique.iter().map(|message| format!("- {}", message)).collect();
+            format!("Apply {} changes\n\n{}", unique.len(), body.join("\n"))
+        }
+    }
+}
+
+fn is_hidden(relative: &Path) -> bool {
+    relative.components().any(|component| match component {
+        Component::Normal(part) => part.to_string_lossy().starts_with('.'),
+        _ => false,
+    })
+}
+
+fn to_git_path(path: &Path) -> String {
+    path.components()
+        .filter_map(|component| match component {
+            Component::Normal(part) => Some(part.to_string_lossy().to_string()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CardVersion {
+    commit: String,
+    message: String,
+    author: String,
+    // Seconds since the Unix epoch
+    time: i64,
+}
+
+// Commits that changed the card at `relative_path` (frontend form), newest first
+pub fn card_history(tasks_root: &Path, relative_path: &str) -> Result<Vec<CardVersion>, git2::Error> {
+    let (repo, prefix) = open_or_init(tasks_root)?;
+    let git_path = git_path(&prefix, relative_path);
+
+    let mut revwalk = repo.revwalk()?;
+    if revwalk.push_head().is_err() {
+        return Ok(Vec::new());
+    }
+    revwalk.set_sorting(Sort::TIME)?;
+
+    let mut versions = Vec::new();
+    for oid in revwalk.take(MAX_HISTORY) {
+        let commit = repo.find_commit(oid?)?;
+        let blob = blob_id(&commit, &git_path);
+        let parent_blob = commit.parent(0).ok().and_then(|parent| blob_id(&parent, &git_path));
+
+        if blob.is_some() && blob != parent_blob {
+            versions.push(CardVersion {
+                commit: commit.id().to_string(),
+                message: commit.message().unwrap_or_default().trim().to_string(),
+                author: commit.author().name().unwrap_or_default().to_string(),
+                time: commit.time().seconds(),
+            });
+        }
+    }
+
+    Ok(versions)
+}
+
+// Unified diff of a card between two commits. `to: None` compares with the
+// file as it currently is on disk.
+pub fn card_diff(tasks_root: &Path, relative_path: &str, from: &str, to: Option<&str>) -> Result<String, git2::Error> {
+    let (repo, prefix) = open_or_init(tasks_root)?;
+    let git_path = git_path(&prefix, relative_path);
+
+    let old = card_content(&repo, &git_path, from)?;
+    let new = match to {
+        Some(to) => card_content(&repo, &git_path, to)?,
+        None => std::fs::read(tasks_root.join(relative_path.trim_start_matches('/'))).unwrap_or_default(),
+    };
+
+    let mut options = DiffOptions::new();
+    let path = Path::new(&git_path);
+    let mut patch = Patch::from_buffers(&old, Some(path), &new, Some(path), Some(&mut options))?;
+    let buf = patch.to_buf()?;
+
+    Ok(String::from_utf8_lossy(&buf).to_string())
+}
+
+// Content of the card at `relative_path` in `commit`
+pub fn card_at(tasks_root: &Path, relative_path: &str, commit: &str) -> Result<Vec<u8>, git2::Error> {
+    let (repo, prefix) = open_or_init(tasks_root)?;
+    let git_path = git_path(&prefix, relative_path);
+    card_content(&repo, &git_path, commit)
+}
+
+fn card_content(repo: &Repository, git_path: &str, commit: &str) -> Result<Vec<u8>, git2::Error> {
+    let commit = repo.find_commit(Oid::from_str(commit)?)?;
+    let blob = blob_id(&commit, git_path)
+        .ok_or_else(|| git2::Error::from_str(&format!("{} does not exist in {}", git_path, commit.id())))?;
+    Ok(repo.find_blob(blob)?.content().to_vec())
+}
+
+fn blob_id(commit: &git2::Commit, git_path: &str) -> Option<Oid> {
+    commit.tree().ok()?.get_path(Path::new(git_path)).ok().map(|entry| entry.id())
+}
+
+// Path in the repository of a card, `prefix` being where the tasks directory is
+fn git_path(prefix: &Path, relative_path: &str) -> String {
+    to_git_path(&prefix.join(relative_path.trim_start_matches('/')))
+}
+
+// Commit messages describing the mutating commands, e.g. "Move card X from Backlog to Done".
+// Paths use the frontend form "/board/lane/card.md".
+pub fn describe_create(path: &str, is_file: bool) -> String {
+    let (lane, name) = split(path);
+    if is_file {
+        format!("Create card {} in {}", card_name(name), lane)
+    } else {
+        format!("Create lane {}", name)
+    }
+}
+
+pub fn describe_update(old_path: &str, new_path: &str, is_file: bool) -> String {
+    let (old_lane, old_name) = split(old_path);
+    let (new_lane, new_name) = split(new_path);
+
+    if !is_file {
+        return format!("Rename lane {} to {}", old_name, new_name);
+    }
+    if old_path == new_path {
+        return format!("Edit card {}", card_name(new_name));
+    }
+    if old_lane != new_lane && old_name == new_name {
+        return format!("Move card {} from {} to {}", card_name(new_name), old_lane, new_lane);
+    }
+    if old_lane != new_lane {
+        return format!("Move card {} from {} to {} as {}", card_name(old_name), old_lane, new_lane, card_name(new_name));
+    }
+    format!("Rename card {} to {}", card_name(old_name), card_name(new_name))
+}
+
+pub fn describe_delete(path: &str, is_file: bool) -> String {
+    let (lane, name) = split(path);
+    if is_file {
+        format!("Delete card {} from {}", card_name(name), lane)
+    } else {
+        format!("Delete lane {}", name)
+    }
+}
+
+pub fn describe_restore(path: &str, is_file: bool) -> String {
+    let (lane, name) = split(path);
+    if is_file {
+        format!("Restore card {} to {}", card_name(name), lane)
+    } else {
+        format!("Restore lane {}", name)
+    }
+}
+
+pub fn describe_revert(path: &str, commit: &str) -> String {
+    let (_, name) = split(path);
+    format!("Revert card {} to {}", card_name(name), commit.get(..7).unwrap_or(commit))
+}
+
+// Splits "/board/lane/card.md" into its parent's name and its own name
+fn split(path: &str) -> (&str, &str) {
+    let mut parts = path.trim_matches('/').rsplit('/');
+    let name = parts.next().unwrap_or_default();
+    let parent = parts.next().unwrap_or_default();
+    (parent, name)
+}
+
+fn card_name(file_name: &str) -> &str {
+    file_name.strip_suffix(".md").unwrap_or(file_name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+    use git2::{IndexAddOption, Status};
+
+    fn head_message(repo: &Repository) -> String {
+        repo.head().unwrap().peel_to_commit().unwrap().message().unwrap().to_string()
+    }
+
+    fn commit_count(repo: &Repository) -> usize {
+        let mut revwalk = repo.revwalk().unwrap();
+        revwalk.push_head().unwrap();
+        revwalk.count()
+    }
+
+    fn tree_has(repo: &Repository, path: &str) -> bool {
+        repo.head().unwrap().peel_to_tree().unwrap().get_path(Path::new(path)).is_ok()
+    }
+
+    // Commits everything like `git add -A && git commit` would
+    fn commit_all(repo: &Repository, message: &str) {
+        let mut index = repo.index().unwrap();
+        index.add_all(["*"], IndexAddOption::DEFAULT, None).unwrap();
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature = Signature::now("User", "user@localhost").unwrap();
+        let parent = repo.head().ok().map(|head| head.peel_to_commit().unwrap());
+        let parents: Vec<_> = parent.iter().collect();
+        repo.commit(Some("HEAD"), &signature, &signature, message, &tree, &parents).unwrap();
+    }
+
+    #[test]
+    fn commits_changes_and_keeps_the_index_in_step() {
+        let dir = TempDir::new("history-commit");
+        dir.write("board/lane/a.md", "a");
+        commit(dir.path(), &["Create card a in lane".to_string()]).unwrap();
+
+        dir.write("board/lane/a.md", "a, edited");
+        dir.write("board/lane/b.md", "b");
+        commit(dir.path(), &["Edit card a".to_string()]).unwrap();
+
+        let repo = Repository::open(dir.path()).unwrap();
+        assert_eq!(commit_count(&repo), 2);
+        assert_eq!(head_message(&repo), "Edit card a");
+        assert!(tree_has(&repo, "board/lane/b.md"));
+        assert!(repo.statuses(None).unwrap().is_empty());
+    }
+
+    #[test]
+    fn skips_commits_when_nothing_changed() {
+        let dir = TempDir::new("history-unchanged");
+        dir.write("board/lane/a.md", "a");
+        commit(dir.path(), &["Create card a in lane".to_string()]).unwrap();
+        commit(dir.path(), &["Edit card a".to_string()]).unwrap();
+
+        let repo = Repository::open(dir.path()).unwrap();
+        assert_eq!(commit_count(&repo), 1);
+    }
+
+    #[test]
+    fn leaves_hidden_entries_alone() {
+        let dir = TempDir::new("history-hidden");
+        dir.write("board/lane/a.md", "a");
+        dir.write("notes/.keep", "kept by hand");
+        let repo = Repository::init(dir.path()).unwrap();
+        commit_all(&repo, "Add notes");
+
+        dir.write(".trash/1/card.md", "trashed");
+        dir.write("board/lane/.a.md.tmp", "in flight");
+        fs::remove_file(dir.path().join("notes/.keep")).unwrap();
+        dir.write("board/lane/a.md", "a, edited");
+        commit(dir.path(), &["Edit card a".to_string()]).unwrap();
+
+        assert_eq!(commit_count(&repo), 2);
+        assert!(!tree_has(&repo, ".trash/1/card.md"));
+        assert!(!tree_has(&repo, "board/lane/.a.md.tmp"));
+        // Committed by hand, so it stays even though it is gone from disk
+        assert!(tree_has(&repo, "notes/.keep"));
+    }
+
+    #[test]
+    fn versions_boards_inside_a_larger_repository() {
+        let dir = TempDir::new("history-nested");
+        let repo = Repository::init(dir.path()).unwrap();
+        dir.write("README.md", "readme");
+        commit_all(&repo, "Add readme");
+
+        // Staged by the user, it must stay staged and out of the app's commits
+        dir.write("README.md", "readme, edited");
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("README.md")).unwrap();
+        index.write().unwrap();
+
+        let tasks_root = dir.path().join("tasks");
+        dir.write("tasks/board/lane/a.md", "a");
+        commit(&tasks_root, &["Create card a in lane".to_string()]).unwrap();
+
+        assert!(!tasks_root.join(".git").exists());
+        assert_eq!(head_message(&repo), "Create card a in lane");
+        assert!(tree_has(&repo, "tasks/board/lane/a.md"));
+        let readme = repo.head().unwrap().peel_to_tree().unwrap().get_path(Path::new("README.md")).unwrap();
+        assert_eq!(repo.find_blob(readme.id()).unwrap().content(), b"readme");
+
+        repo.index().unwrap().read(true).unwrap();
+        assert_eq!(repo.status_file(Path::new("README.md")).unwrap(), Status::INDEX_MODIFIED);
+        assert_eq!(repo.status_file(Path::new("tasks/board/lane/a.md")).unwrap(), Status::CURRENT);
+
+        let versions = card_history(&tasks_root, "/board/lane/a.md").unwrap();
+        assert_eq!(versions.len(), 1);
+        assert_eq!(card_at(&tasks_root, "/board/lane/a.md", &versions[0].commit).unwrap(), b"a");
+    }
+
+    #[test]
+    fn tells_hidden_paths() {
+        assert!(is_hidden(Path::new(".trash")));
+        assert!(is_hidden(Path::new("board/.git/config")));
+        assert!(is_hidden(Path::new("board/lane/.card.md.tmp")));
+        assert!(!is_hidden(Path::new("board/lane/card.md")));
+        assert!(!is_hidden(Path::new("board/lane/v1.2.md")));
+        assert!(!is_hidden(Path::new("")));
+    }
+
+    #[test]
+    fn coalesces_commit_messages() {
+        let messages = |messages: &[&str]| commit_message(&messages.iter().map(|message| message.to_string()).collect::<Vec<_>>());
+
+        assert_eq!(messages(&["Edit card a"]), "Edit card a");
+        assert_eq!(messages(&["Edit card a", "Edit card a", "Edit card a"]), "Edit card a");
+        assert_eq!(
+            messages(&["Edit card a", "Move card a from Backlog to Done", "Edit card a"]),
+            "Apply 2 changes\n\n- Edit card a\n- Move card a from Backlog to Done"
+        );
+    }
+
+    #[test]
+    fn describes_changes() {
+        assert_eq!(describe_create("/board/Backlog/a.md", true), "Create card a in Backlog");
+        assert_eq!(describe_update("/board/Backlog/a.md", "/board/Done/a.md", true), "Move card a from Backlog to Done");
+        assert_eq!(describe_update("/board/Backlog/a.md", "/board/Done/b.md", true), "Move card a from Backlog to Done as b");
+        assert_eq!(describe_update("/board/Backlog/a.md", "/board/Backlog/b.md", true), "Rename card a to b");
+        assert_eq!(describe_update("/board/Backlog", "/board/Todo", false), "Rename lane Backlog to Todo");
+        assert_eq!(describe_revert("/board/Backlog/a.md", "0123456789abcdef"), "Revert card a to 0123456");
+    }
+}
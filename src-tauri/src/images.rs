@@ -0,0 +1,823 @@
+use crate::paths;
+use crate::storage;
+use crate::thumbnails;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tauri::http::{header, Method, Request, Response, StatusCode};
+use uuid::Uuid;
+
+// Scheme the webview loads images from, as `tasksmd-image://localhost/<name>`
+// or `http://tasksmd-image.localhost/<name>` on Windows
+pub const URI_SCHEME: &str = "tasksmd-image";
+
+// Images are uploaded before the card referencing them is saved, so recent
+// ones are never considered unused
+const GRACE_PERIOD: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UnusedImage {
+    pub name: String,
+    pub size: u64,
+}
+
+#[derive(Serialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Cleanup {
+    // Removed images, or the ones that would be removed on a dry run
+    pub images: Vec<UnusedImage>,
+    pub reclaimed_bytes: u64,
+    // Cards that could not be read, in the frontend form. As they may refer
+    // to any image, nothing is removed when there are some.
+    pub unreadable_cards: Vec<String>,
+}
+
+// Formats accepted for uploads, recognized by their first bytes whatever the
+// file is called. SVG is left out as it can carry scripts.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ImageFormat {
+    Png,
+    Jpeg,
+    Gif,
+    Webp,
+    Avif,
+    Bmp,
+    Ico,
+}
+
+// Bytes `ImageFormat::sniff` needs from the start of a file
+pub const SNIFF_LENGTH: usize = 22;
+
+impl ImageFormat {
+    // `head` is the start of the file and `length` its whole size, which BMP
+    // and ICO headers are checked against
+    pub fn sniff(head: &[u8], length: u64) -> Option<ImageFormat> {
+        let le_u32 = |bytes: &[u8]| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        match head {
+            [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n', ..] => Some(ImageFormat::Png),
+            [0xff, 0xd8, 0xff, ..] => Some(ImageFormat::Jpeg),
+            [b'G', b'I', b'F', b'8', b'7' | b'9', b'a', ..] => Some(ImageFormat::Gif),
+            [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => Some(ImageFormat::Webp),
+            [_, _, _, _, b'f', b't', b'y', b'p', b'a', b'v', b'i', b'f' | b's', ..] => Some(ImageFormat::Avif),
+            // The file size, then the size of the DIB header for the core, info, v4 and v5 variants
+            [b'B', b'M', ..] if head.len() >= 18
+                && u64::from(le_u32(&head[2..6])) == length
+                && matches!(le_u32(&head[14..18]), 12 | 40 | 108 | 124) => Some(ImageFormat::Bmp),
+            // The image count, then the first directory entry, whose reserved
+            // byte is zero and whose image lies after the directory and
+            // within the file
+            [0, 0, 1, 0, count_low, count_high, _, _, _, 0, ..] if head.len() >= 22 => {
+                let count = u16::from_le_bytes([*count_low, *count_high]);
+                let (size, offset) = (u64::from(le_u32(&head[14..18])), u64::from(le_u32(&head[18..22])));
+                let directory_end = 6 + 16 * u64::from(count);
+                (count > 0 && size > 0 && offset >= directory_end && offset + size <= length).then_some(ImageFormat::Ico)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            ImageFormat::Png => "png",
+            ImageFormat::Jpeg => "jpg",
+            ImageFormat::Gif => "gif",
+            ImageFormat::Webp => "webp",
+            ImageFormat::Avif => "avif",
+            ImageFormat::Bmp => "bmp",
+            ImageFormat::Ico => "ico",
+        }
+    }
+
+    pub fn mime_type(self) -> &'static str {
+        match self {
+            ImageFormat::Png => "image/png",
+            ImageFormat::Jpeg => "image/jpeg",
+            ImageFormat::Gif => "image/gif",
+            ImageFormat::Webp => "image/webp",
+            ImageFormat::Avif => "image/avif",
+            ImageFormat::Bmp => "image/bmp",
+            ImageFormat::Ico => "image/x-icon",
+        }
+    }
+}
+
+// Reasons an upload is refused, `name` being the file name sent by the client
+#[derive(Debug)]
+pub enum ImageError {
+    Unsupported(String),
+    TooLarge { name: String, size: u64, limit: u64 },
+}
+
+impl fmt::Display for ImageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImageError::Unsupported(name) => write!(f, "\"{}\" is not a PNG, JPEG, GIF, WebP, AVIF, BMP or ICO image", name),
+            ImageError::TooLarge { name, size, limit } => {
+                write!(f, "\"{}\" is {}, larger than the {} limit", name, format_size(*size), format_size(*limit))
+            }
+        }
+    }
+}
+
+impl std::error::Error for ImageError {}
+
+// Checks an upload before it is stored. `max_size` is in bytes, 0 meaning
+// no limit.
+pub fn validate(content: &[u8], name: &str, max_size: u64) -> Result<ImageFormat, ImageError> {
+    let size = content.len() as u64;
+    if max_size > 0 && size > max_size {
+        return Err(ImageError::TooLarge { name: name.to_string(), size, limit: max_size });
+    }
+    ImageFormat::sniff(content, size).ok_or_else(|| ImageError::Unsupported(name.to_string()))
+}
+
+// Answers an image request of the webview with the file itself, so it is
+// never copied through a JSON array. `?size=256` asks for a thumbnail of
+// about that size instead. Single byte ranges are honored, and as a name
+// never gets other content, responses are cached for good.
+pub fn serve(images_dir: &Path, thumbnail_config: &thumbnails::Config, request: &Request<Vec<u8>>) -> Response<Vec<u8>> {
+    respond(images_dir, thumbnail_config, request).unwrap_or_else(|status| {
+        Response::builder()
+            .status(status)
+            .header(header::CONTENT_TYPE, "text/plain")
+            .body(status.canonical_reason().unwrap_or_default().as_bytes().to_vec())
+            .unwrap()
+    })
+}
+
+fn respond(images_dir: &Path, thumbnail_config: &thumbnails::Config, request: &Request<Vec<u8>>) -> Result<Response<Vec<u8>>, StatusCode> {
+    if request.method() != Method::GET && request.method() != Method::HEAD {
+        return Err(StatusCode::METHOD_NOT_ALLOWED);
+    }
+    let name = percent_decode(request.uri().path().trim_start_matches('/')).ok_or(StatusCode::BAD_REQUEST)?;
+    if name.split('/').any(|part| part.starts_with('.')) {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    let path = paths::resolve_entry(images_dir, &name).map_err(|_| StatusCode::NOT_FOUND)?;
+    let mut file = fs::File::open(&path).map_err(|_| StatusCode::NOT_FOUND)?;
+    let mut metadata = file.metadata().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if !metadata.is_file() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    // Files uploaded before their content was checked are sniffed again, so
+    // one that is not an image is never handed to the webview
+    let mut head = Vec::new();
+    (&mut file).take(SNIFF_LENGTH as u64).read_to_end(&mut head).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let mut format = ImageFormat::sniff(&head, metadata.len()).ok_or(StatusCode::UNSUPPORTED_MEDIA_TYPE)?;
+
+    let size = request.uri().query()
+        .and_then(|query| query.split('&').find_map(|pair| pair.strip_prefix("size=")))
+        .and_then(|size| size.parse().ok())
+        .and_then(|size| thumbnail_config.size_for(size));
+    if let Some(thumbnail) = size.and_then(|size| thumbnails::get(images_dir, &path, format, size, thumbnail_config.format)) {
+        file = fs::File::open(&thumbnail).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        metadata = file.metadata().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        format = thumbnail_config.format;
+    }
+
+    let length = metadata.len();
+    let modified = metadata.modified().ok().and_then(|modified| modified.duration_since(UNIX_EPOCH).ok()).unwrap_or_default();
+    let etag = format!("\"{:x}-{:x}\"", length, modified.as_secs());
+    let response = Response::builder()
+        .header(header::ACCEPT_RANGES, "bytes")
+        .header(header::CACHE_CONTROL, "public, max-age=31536000, immutable")
+        .header(header::ETAG, &etag)
+        .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*");
+
+    if request.headers().get(header::IF_NONE_MATCH).is_some_and(|value| value.as_bytes() == etag.as_bytes()) {
+        return Ok(response.status(StatusCode::NOT_MODIFIED).body(Vec::new()).unwrap());
+    }
+
+    // An empty file has no byte range to send, whatever was asked for
+    if length == 0 {
+        let response = response.status(StatusCode::OK).header(header::CONTENT_TYPE, format.mime_type()).header(header::CONTENT_LENGTH, 0)
+            .header(header::X_CONTENT_TYPE_OPTIONS, "nosniff");
+        return Ok(response.body(Vec::new()).unwrap());
+    }
+
+    let range = request.headers().get(header::RANGE).and_then(|value| value.to_str().ok()).map(|value| parse_range(value, length));
+    let (response, start, end) = match range {
+        Some(Some(Ok((start, end)))) => (
+            response.status(StatusCode::PARTIAL_CONTENT).header(header::CONTENT_RANGE, format!("bytes {}-{}/{}", start, end, length)),
+            start,
+            end,
+        ),
+        Some(Some(Err(()))) => {
+            let response = response.status(StatusCode::RANGE_NOT_SATISFIABLE).header(header::CONTENT_RANGE, format!("bytes */{}", length));
+            return Ok(response.body(Vec::new()).unwrap());
+        }
+        _ => (response.status(StatusCode::OK), 0, length - 1),
+    };
+
+    let mut body = Vec::new();
+    if request.method() == Method::GET {
+        file.seek(SeekFrom::Start(start)).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        file.take(end - start + 1).read_to_end(&mut body).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    }
+    let response = response
+        .header(header::CONTENT_TYPE, format.mime_type())
+        .header(header::CONTENT_LENGTH, end - start + 1)
+        .header(header::X_CONTENT_TYPE_OPTIONS, "nosniff");
+
+    Ok(response.body(body).unwrap())
+}
+
+// The inclusive bounds of a `bytes=start-end`, `bytes=start-` or
+// `bytes=-suffix` header, `None` when it should be ignored and the whole file
+// sent, as for several ranges, and `Err` when it lies outside the file
+fn parse_range(value: &str, length: u64) -> Option<Result<(u64, u64), ()>> {
+    let (start, end) = value.trim().strip_prefix("bytes=")?.split_once('-')?;
+    let (start, end) = (start.trim(), end.trim());
+    if end.contains(',') {
+        return None;
+    }
+    let bounds = match (start.parse::<u64>(), end.parse::<u64>()) {
+        (Ok(start), Ok(end)) if start <= end => (start, end.min(length.saturating_sub(1))),
+        (Ok(start), Err(_)) if end.is_empty() => (start, length.saturating_sub(1)),
+        (Err(_), Ok(suffix)) if start.is_empty() && suffix > 0 => (length.saturating_sub(suffix), length.saturating_sub(1)),
+        _ => return None,
+    };
+    if bounds.0 >= length {
+        return Some(Err(()));
+    }
+    Some(Ok(bounds))
+}
+
+// Decodes the `%XX` escapes of a URI path or header
+pub fn percent_decode(value: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(value.len());
+    let mut rest = value.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        if byte == b'%' {
+            // `from_str_radix` alone would take a sign as in `%+1`
+            let hex = tail.get(..2).filter(|hex| hex.iter().all(u8::is_ascii_hexdigit))?;
+            bytes.push(u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok()?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(byte);
+            rest = tail;
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+// Images are named after the SHA-256 of their content, so the same image
+// uploaded to several cards is stored once
+pub fn content_name(content: &[u8], extension: &str) -> String {
+    format!("{:x}.{}", Sha256::digest(content), extension)
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Migration {
+    pub renamed: Vec<RenamedImage>,
+    // Cards whose image references were rewritten, in the frontend form
+    pub updated_cards: Vec<String>,
+    // Space freed by duplicates
+    pub reclaimed_bytes: u64,
+    // Files left under their old name as their content is not a supported
+    // image, which is never given a content name the webview would trust
+    pub skipped: Vec<String>,
+    // Cards that could not be read and rewritten, in the frontend form. The
+    // old names are then kept next to the new ones for them.
+    pub unreadable_cards: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct RenamedImage {
+    pub from: String,
+    pub to: String,
+}
+
+// Renames the images uploaded with random UUID names to their content name,
+// with the extension of the format sniffed from that content, and rewrites the cards referring to them, including the ones in the trash.
+// The new names are created before the cards are rewritten and the old ones
+// removed last, so an interruption never leaves a card pointing nowhere.
+pub fn migrate(images_dir: &Path, tasks_root: &Path) -> io::Result<Migration> {
+    let mut migration = Migration {
+        renamed: Vec::new(),
+        updated_cards: Vec::new(),
+        reclaimed_bytes: 0,
+        skipped: Vec::new(),
+        unreadable_cards: Vec::new(),
+    };
+    let entries = match fs::read_dir(images_dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(migration),
+        Err(e) => return Err(e),
+    };
+
+    let mut migrated = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        match name.rsplit_once('.') {
+            Some((stem, _)) if Uuid::parse_str(stem).is_ok() => {}
+            _ => continue,
+        }
+        if !entry.file_type()?.is_file() {
+            continue;
+        }
+
+        let content = fs::read(entry.path())?;
+        let format = match ImageFormat::sniff(&content, content.len() as u64) {
+            Some(format) => format,
+            None => {
+                migration.skipped.push(name);
+                continue;
+            }
+        };
+        let new_name = content_name(&content, format.extension());
+        let new_path = images_dir.join(&new_name);
+        if new_path.exists() {
+            migration.reclaimed_bytes += content.len() as u64;
+        } else if fs::hard_link(entry.path(), &new_path).is_err() {
+            storage::write_atomic(&new_path, &content)?;
+        }
+        migrated.push(entry.path());
+        migration.renamed.push(RenamedImage { from: name, to: new_name });
+    }
+    if migration.renamed.is_empty() {
+        return Ok(migration);
+    }
+
+    let renames: HashMap<&str, &str> = migration.renamed.iter().map(|image| (image.from.as_str(), image.to.as_str())).collect();
+    let mut cards = Cards::default();
+    read_cards(tasks_root, &mut cards, &mut HashSet::new())?;
+    migration.unreadable_cards = cards.unreadable.iter().map(|path| paths::to_relative(tasks_root, path)).collect();
+    for (path, content) in cards.readable {
+        let mut updated = content.clone();
+        for (from, to) in &renames {
+            if updated.contains(from) {
+                updated = updated.replace(from, to);
+            }
+        }
+        if updated != content {
+            storage::write_atomic(&path, updated.as_bytes())?;
+            migration.updated_cards.push(paths::to_relative(tasks_root, &path));
+        }
+    }
+
+    if migration.unreadable_cards.is_empty() {
+        for path in migrated {
+            fs::remove_file(path)?;
+        }
+        thumbnails::prune(images_dir)?;
+    }
+
+    Ok(migration)
+}
+
+// Removes the images of `images_dir` that no card under `tasks_root`
+// mentions, or only lists them when `dry_run` is set or a card could not be
+// read. Cards in the trash count, so restoring one brings its images back
+// along.
+pub fn clean(images_dir: &Path, tasks_root: &Path, dry_run: bool) -> io::Result<Cleanup> {
+    let mut cleanup = Cleanup::default();
+    let entries = match fs::read_dir(images_dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(cleanup),
+        Err(e) => return Err(e),
+    };
+
+    let mut cards = Cards::default();
+    read_cards(tasks_root, &mut cards, &mut HashSet::new())?;
+    cleanup.unreadable_cards = cards.unreadable.iter().map(|path| paths::to_relative(tasks_root, path)).collect();
+    let dry_run = dry_run || !cleanup.unreadable_cards.is_empty();
+
+    let now = SystemTime::now();
+    for entry in entries {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        let metadata = entry.metadata()?;
+        if !metadata.is_file() || name.starts_with('.') {
+            continue;
+        }
+        let age = metadata.modified().ok().and_then(|modified| now.duration_since(modified).ok()).unwrap_or_default();
+        if age < GRACE_PERIOD || cards.readable.iter().any(|(_, content)| content.contains(&name)) {
+            continue;
+        }
+
+        if !dry_run {
+            fs::remove_file(entry.path())?;
+        }
+        cleanup.reclaimed_bytes += metadata.len();
+        cleanup.images.push(UnusedImage { name, size: metadata.len() });
+    }
+    if !dry_run {
+        thumbnails::prune(images_dir)?;
+    }
+
+    Ok(cleanup)
+}
+
+// Cleans `images_dir` every `interval` in the background, like the
+// `LOCAL_IMAGES_CLEANUP_INTERVAL` job of the web version
+pub fn start_cleanup(images_dir: PathBuf, tasks_root: PathBuf, interval: Duration) {
+    thread::spawn(move || loop {
+        thread::sleep(interval);
+        match clean(&images_dir, &tasks_root, false) {
+            Ok(cleanup) if !cleanup.unreadable_cards.is_empty() => log::warn!(
+                "Kept the unused images of {} as some cards could not be read: {}",
+                images_dir.display(),
+                cleanup.unreadable_cards.join(", "),
+            ),
+            Ok(cleanup) if !cleanup.images.is_empty() => log::info!(
+                "Removed {} unused images from {}, reclaiming {}",
+                cleanup.images.len(),
+                images_dir.display(),
+                format_size(cleanup.reclaimed_bytes),
+            ),
+            Ok(_) => {}
+            Err(e) => log::error!("Failed to clean up unused images in {}: {}", images_dir.display(), e),
+        }
+    });
+}
+
+// Every card with its content, image references being matched by file name
+// whatever URL form they are written in
+#[derive(Default)]
+struct Cards {
+    readable: Vec<(PathBuf, String)>,
+    // Cards that are not UTF-8 or could not be opened
+    unreadable: Vec<PathBuf>,
+}
+
+fn read_cards(dir: &Path, cards: &mut Cards, visited: &mut HashSet<PathBuf>) -> io::Result<()> {
+    // Symlinked lanes pointing back up the tree would otherwise never end
+    if !visited.insert(fs::canonicalize(dir)?) {
+        return Ok(());
+    }
+
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        if entry.file_name() == ".git" {
+            continue;
+        }
+        if path.is_dir() {
+            read_cards(&path, cards, visited)?;
+        } else if path.extension().is_some_and(|extension| extension == "md") {
+            match fs::read_to_string(&path) {
+                Ok(content) => cards.readable.push((path, content)),
+                Err(_) => cards.unreadable.push(path),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    const PNG: &[u8] = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+    const OTHER_PNG: &[u8] = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR, another one";
+    const FIRST: &str = "0e9c1a4a-3f0b-4f57-9c61-6d3e5d6f1a01";
+    const SECOND: &str = "5b2d7c8e-1a9f-4e3b-8d2c-7f6a5e4d3c02";
+    const THIRD: &str = "9f8e7d6c-5b4a-4321-8fed-cba987654303";
+
+    #[test]
+    fn migrates_images_to_their_content_name() {
+        let dir = TempDir::new("images-migrate");
+        let (images, tasks) = (dir.path().join("images"), dir.path().join("tasks"));
+        // Named .jpg, but the content decides the extension
+        dir.write(&format!("images/{}.jpg", FIRST), PNG);
+        dir.write(&format!("images/{}.png", SECOND), OTHER_PNG);
+        dir.write("images/kept.png", PNG);
+        dir.write("tasks/board/lane/card.md", format!("![](/_api/image/{}.jpg)\n", FIRST));
+        dir.write("tasks/board/lane/other.md", "No image");
+
+        let migration = migrate(&images, &tasks).unwrap();
+
+        let first = content_name(PNG, "png");
+        let second = content_name(OTHER_PNG, "png");
+        assert_eq!(migration.renamed.len(), 2);
+        assert!(migration.renamed.iter().any(|image| image.from == format!("{}.jpg", FIRST) && image.to == first));
+        assert!(migration.renamed.iter().any(|image| image.from == format!("{}.png", SECOND) && image.to == second));
+        assert_eq!(migration.updated_cards, vec!["/board/lane/card.md"]);
+        assert_eq!(fs::read_to_string(tasks.join("board/lane/card.md")).unwrap(), format!("![](/_api/image/{})\n", first));
+        assert_eq!(fs::read(images.join(&first)).unwrap(), PNG);
+        assert_eq!(fs::read(images.join(&second)).unwrap(), OTHER_PNG);
+        assert!(!images.join(format!("{}.jpg", FIRST)).exists());
+        assert!(!images.join(format!("{}.png", SECOND)).exists());
+        assert!(images.join("kept.png").exists());
+    }
+
+    #[test]
+    fn merges_duplicate_images() {
+        let dir = TempDir::new("images-migrate-duplicates");
+        let (images, tasks) = (dir.path().join("images"), dir.path().join("tasks"));
+        dir.write(&format!("images/{}.png", FIRST), PNG);
+        dir.write(&format!("images/{}.png", SECOND), PNG);
+        dir.write("tasks/board/lane/card.md", format!("![](/_api/image/{}.png) ![](/_api/image/{}.png)", FIRST, SECOND));
+
+        let migration = migrate(&images, &tasks).unwrap();
+
+        let name = content_name(PNG, "png");
+        assert_eq!(migration.reclaimed_bytes, PNG.len() as u64);
+        assert_eq!(fs::read_dir(&images).unwrap().count(), 1);
+        assert!(images.join(&name).exists());
+        assert_eq!(
+            fs::read_to_string(tasks.join("board/lane/card.md")).unwrap(),
+            format!("![](/_api/image/{}) ![](/_api/image/{})", name, name),
+        );
+    }
+
+    #[test]
+    fn skips_files_that_are_not_images() {
+        let dir = TempDir::new("images-migrate-spoofed");
+        let (images, tasks) = (dir.path().join("images"), dir.path().join("tasks"));
+        dir.write(&format!("images/{}.png", FIRST), "<html><script>alert(1)</script></html>");
+        dir.write(&format!("images/{}.svg", SECOND), "<svg xmlns=\"http://www.w3.org/2000/svg\"></svg>");
+        dir.write(&format!("images/{}.png", THIRD), PNG);
+        dir.write("tasks/board/lane/card.md", format!("![](/_api/image/{}.png)", FIRST));
+
+        let migration = migrate(&images, &tasks).unwrap();
+
+        let mut skipped = migration.skipped.clone();
+        skipped.sort();
+        assert_eq!(skipped, vec![format!("{}.png", FIRST), format!("{}.svg", SECOND)]);
+        assert_eq!(migration.renamed.len(), 1);
+        assert!(migration.updated_cards.is_empty());
+        assert!(images.join(format!("{}.png", FIRST)).exists());
+        assert!(images.join(format!("{}.svg", SECOND)).exists());
+    }
+
+    // Writes an image old enough to be cleaned up
+    fn write_old_image(dir: &TempDir, name: &str) {
+        let path = dir.write(&format!("images/{}", name), PNG);
+        let modified = SystemTime::now() - GRACE_PERIOD - Duration::from_secs(60);
+        fs::File::options().write(true).open(path).unwrap().set_modified(modified).unwrap();
+    }
+
+    #[test]
+    fn cleans_images_no_card_refers_to() {
+        let dir = TempDir::new("images-clean");
+        let (images, tasks) = (dir.path().join("images"), dir.path().join("tasks"));
+        write_old_image(&dir, "used.png");
+        write_old_image(&dir, "trashed.png");
+        write_old_image(&dir, "unused.png");
+        dir.write("images/recent.png", PNG);
+        dir.write("tasks/board/lane/card.md", "![](/_api/image/used.png)");
+        dir.write("tasks/.trash/card.md", "![](/_api/image/trashed.png)");
+
+        let cleanup = clean(&images, &tasks, true).unwrap();
+        assert_eq!(cleanup.images.iter().map(|image| image.name.as_str()).collect::<Vec<_>>(), vec!["unused.png"]);
+        assert!(images.join("unused.png").exists());
+
+        let cleanup = clean(&images, &tasks, false).unwrap();
+        assert_eq!(cleanup.reclaimed_bytes, PNG.len() as u64);
+        assert!(!images.join("unused.png").exists());
+        assert!(images.join("used.png").exists());
+        assert!(images.join("trashed.png").exists());
+        assert!(images.join("recent.png").exists());
+    }
+
+    #[test]
+    fn keeps_every_image_when_a_card_is_unreadable() {
+        let dir = TempDir::new("images-clean-unreadable");
+        let (images, tasks) = (dir.path().join("images"), dir.path().join("tasks"));
+        write_old_image(&dir, "unused.png");
+        dir.write("tasks/board/lane/card.md", b"\xff\xfe not UTF-8 ![](/_api/image/unused.png)");
+        dir.write("tasks/board/lane/other.md", "No image");
+
+        let cleanup = clean(&images, &tasks, false).unwrap();
+        assert_eq!(cleanup.unreadable_cards, vec!["/board/lane/card.md"]);
+        assert_eq!(cleanup.images.len(), 1);
+        assert!(images.join("unused.png").exists());
+    }
+
+    #[test]
+    fn keeps_old_names_when_a_card_is_unreadable() {
+        let dir = TempDir::new("images-migrate-unreadable");
+        let (images, tasks) = (dir.path().join("images"), dir.path().join("tasks"));
+        dir.write(&format!("images/{}.png", FIRST), PNG);
+        dir.write("tasks/board/lane/card.md", format!("![](/_api/image/{}.png)", FIRST));
+        dir.write("tasks/board/lane/other.md", b"\xff\xfe not UTF-8");
+
+        let migration = migrate(&images, &tasks).unwrap();
+        assert_eq!(migration.unreadable_cards, vec!["/board/lane/other.md"]);
+        assert_eq!(migration.updated_cards, vec!["/board/lane/card.md"]);
+        assert!(images.join(format!("{}.png", FIRST)).exists());
+        assert!(images.join(content_name(PNG, "png")).exists());
+    }
+
+    fn bmp(file_size: u32, dib_size: u32) -> Vec<u8> {
+        let mut content = b"BM".to_vec();
+        content.extend(file_size.to_le_bytes());
+        content.extend([0, 0, 0, 0]);
+        content.extend((14 + dib_size).to_le_bytes());
+        content.extend(dib_size.to_le_bytes());
+        content.resize(file_size as usize, 0);
+        content
+    }
+
+    fn ico(count: u16, size: u32, offset: u32, length: usize) -> Vec<u8> {
+        let mut content = vec![0, 0, 1, 0];
+        content.extend(count.to_le_bytes());
+        content.extend([16, 16, 0, 0, 1, 0, 32, 0]);
+        content.extend(size.to_le_bytes());
+        content.extend(offset.to_le_bytes());
+        content.resize(length, 0);
+        content
+    }
+
+    fn sniff(content: &[u8]) -> Option<ImageFormat> {
+        ImageFormat::sniff(content, content.len() as u64)
+    }
+
+    #[test]
+    fn sniffs_formats() {
+        let cases: Vec<(Vec<u8>, ImageFormat)> = vec![
+            (PNG.to_vec(), ImageFormat::Png),
+            (b"\xff\xd8\xff\xe0\0\x10JFIF\0".to_vec(), ImageFormat::Jpeg),
+            (b"GIF87a\x01\0\x01\0".to_vec(), ImageFormat::Gif),
+            (b"GIF89a\x01\0\x01\0".to_vec(), ImageFormat::Gif),
+            (b"RIFF\x24\0\0\0WEBPVP8 ".to_vec(), ImageFormat::Webp),
+            (b"\0\0\0\x1cftypavif\0\0\0\0".to_vec(), ImageFormat::Avif),
+            (b"\0\0\0\x1cftypavis\0\0\0\0".to_vec(), ImageFormat::Avif),
+            (bmp(66, 12), ImageFormat::Bmp),
+            (bmp(70, 40), ImageFormat::Bmp),
+            (bmp(150, 108), ImageFormat::Bmp),
+            (bmp(150, 124), ImageFormat::Bmp),
+            (ico(1, 40, 22, 62), ImageFormat::Ico),
+            (ico(2, 40, 38, 78), ImageFormat::Ico),
+        ];
+        for (content, format) in cases {
+            assert_eq!(sniff(&content), Some(format), "{:?}", content);
+            assert_eq!(ImageFormat::sniff(&content[..content.len().min(SNIFF_LENGTH)], content.len() as u64), Some(format));
+        }
+    }
+
+    #[test]
+    fn refuses_truncated_and_inconsistent_headers() {
+        let cases: Vec<Vec<u8>> = vec![
+            Vec::new(),
+            PNG[..4].to_vec(),
+            b"\xff\xd8".to_vec(),
+            b"GIF8".to_vec(),
+            b"RIFF\x24\0\0\0WEB".to_vec(),
+            b"\0\0\0\x1cftypav".to_vec(),
+            bmp(70, 40)[..16].to_vec(),
+            // The header disagrees with the file size or has an unknown DIB size
+            bmp(70, 40)[..60].to_vec(),
+            bmp(70, 64),
+            ico(1, 40, 22, 62)[..20].to_vec(),
+            // No image, an image running past the end of the file, or one
+            // starting inside the directory
+            ico(0, 40, 22, 62),
+            ico(1, 0, 22, 62),
+            ico(1, 41, 22, 62),
+            ico(1, 40, 6, 62),
+            ico(2, 40, 22, 62),
+            ico(1, u32::MAX, u32::MAX, 62),
+        ];
+        for content in cases {
+            assert_eq!(sniff(&content), None, "{:?}", content);
+        }
+        // A reserved byte that is not zero
+        let mut content = ico(1, 40, 22, 62);
+        content[9] = 1;
+        assert_eq!(sniff(&content), None);
+    }
+
+    #[test]
+    fn refuses_markup_posing_as_images() {
+        let cases: [&[u8]; 6] = [
+            b"<!DOCTYPE html><html><script>alert(1)</script></html>",
+            b"<html><body onload=\"alert(1)\"></body></html>",
+            b"<svg xmlns=\"http://www.w3.org/2000/svg\"><script>alert(1)</script></svg>",
+            b"<?xml version=\"1.0\"?><svg xmlns=\"http://www.w3.org/2000/svg\"/>",
+            b"\xef\xbb\xbf<svg xmlns=\"http://www.w3.org/2000/svg\"/>",
+            b"\0\0\x01\0<html><script>alert(1)</script></html>",
+        ];
+        for content in cases {
+            assert!(matches!(validate(content, "image.png", 0), Err(ImageError::Unsupported(_))), "{:?}", content);
+        }
+    }
+
+    #[test]
+    fn enforces_the_size_limit() {
+        assert!(matches!(validate(PNG, "image.png", 4), Err(ImageError::TooLarge { size: 16, limit: 4, .. })));
+        assert_eq!(validate(PNG, "image.png", 16).unwrap(), ImageFormat::Png);
+        assert_eq!(validate(PNG, "image.png", 0).unwrap(), ImageFormat::Png);
+    }
+
+    fn request(uri: &str, range: Option<&str>) -> Request<Vec<u8>> {
+        let mut request = Request::builder().uri(uri);
+        if let Some(range) = range {
+            request = request.header(header::RANGE, range);
+        }
+        request.body(Vec::new()).unwrap()
+    }
+
+    #[test]
+    fn parses_ranges() {
+        assert_eq!(parse_range("bytes=0-9", 100), Some(Ok((0, 9))));
+        assert_eq!(parse_range("bytes=90-200", 100), Some(Ok((90, 99))));
+        assert_eq!(parse_range("bytes=10-", 100), Some(Ok((10, 99))));
+        assert_eq!(parse_range("bytes=-10", 100), Some(Ok((90, 99))));
+        assert_eq!(parse_range("bytes=-500", 100), Some(Ok((0, 99))));
+        assert_eq!(parse_range("bytes=100-", 100), Some(Err(())));
+        assert_eq!(parse_range("bytes=150-200", 100), Some(Err(())));
+        assert_eq!(parse_range("bytes=0-", 0), Some(Err(())));
+        assert_eq!(parse_range("bytes=-10", 0), Some(Err(())));
+    }
+
+    #[test]
+    fn ignores_ranges_it_does_not_handle() {
+        assert_eq!(parse_range("bytes=0-9,20-29", 100), None);
+        assert_eq!(parse_range("bytes=9-0", 100), None);
+        assert_eq!(parse_range("bytes=-0", 100), None);
+        assert_eq!(parse_range("bytes=-", 100), None);
+        assert_eq!(parse_range("bytes=a-b", 100), None);
+        assert_eq!(parse_range("items=0-9", 100), None);
+    }
+
+    #[test]
+    fn decodes_percent_escapes() {
+        assert_eq!(percent_decode("a%20b.png").as_deref(), Some("a b.png"));
+        assert_eq!(percent_decode("%C3%A9t%c3%a9.png").as_deref(), Some("été.png"));
+        assert_eq!(percent_decode("plain.png").as_deref(), Some("plain.png"));
+        assert_eq!(percent_decode("a%2").as_deref(), None);
+        assert_eq!(percent_decode("a%").as_deref(), None);
+        assert_eq!(percent_decode("a%zz").as_deref(), None);
+        assert_eq!(percent_decode("a%+1").as_deref(), None);
+        assert_eq!(percent_decode("%FF").as_deref(), None);
+    }
+
+    #[test]
+    fn serves_images_and_ranges() {
+        let dir = TempDir::new("images-serve");
+        let name = content_name(PNG, "png");
+        dir.write(&name, PNG);
+        let config = thumbnails::Config { sizes: Vec::new(), format: ImageFormat::Webp };
+
+        let response = serve(dir.path(), &config, &request(&format!("/{}", name), None));
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "image/png");
+        assert_eq!(response.body(), PNG);
+
+        let response = serve(dir.path(), &config, &request(&format!("/{}", name), Some("bytes=1-3")));
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(response.headers()[header::CONTENT_RANGE], format!("bytes 1-3/{}", PNG.len()));
+        assert_eq!(response.body(), b"PNG");
+
+        let response = serve(dir.path(), &config, &request(&format!("/{}", name), Some("bytes=100-")));
+        assert_eq!(response.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+    }
+
+    #[test]
+    fn serves_empty_files_whole() {
+        let dir = TempDir::new("images-serve-empty");
+        let name = content_name(PNG, "png");
+        dir.write(&name, PNG);
+        // A cached thumbnail left empty, newer than its original
+        dir.write(&format!(".thumbnails/{}@256.webp", name), "");
+        let config = thumbnails::Config { sizes: vec![256], format: ImageFormat::Webp };
+
+        for range in [None, Some("bytes=0-"), Some("bytes=-10")] {
+            let response = serve(dir.path(), &config, &request(&format!("/{}?size=256", name), range));
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(response.headers()[header::CONTENT_LENGTH], "0");
+            assert!(response.body().is_empty());
+        }
+    }
+
+    #[test]
+    fn refuses_files_that_are_not_images() {
+        let dir = TempDir::new("images-serve-spoofed");
+        dir.write("page.png", "<html><script>alert(1)</script></html>");
+        let config = thumbnails::Config::default();
+
+        let response = serve(dir.path(), &config, &request("/page.png", None));
+        assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        let response = serve(dir.path(), &config, &request("/.thumbnails/x", None));
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
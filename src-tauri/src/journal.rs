@@ -0,0 +1,271 @@
+use crate::config::ConfigStore;
+use crate::error::CommandError;
+use crate::paths;
+use crate::storage;
+use crate::trash;
+use serde_json::Value;
+use std::collections::VecDeque;
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// Number of undo steps kept
+const MAX_ENTRIES: usize = 100;
+// One user action in the UI can fire several commands (moving a card renames
+// it and then updates `sort.json`), so mutations recorded this close to each
+// other are undone together
+const COALESCE_WINDOW: Duration = Duration::from_secs(1);
+
+#[derive(Clone, Copy, Debug)]
+pub enum ConfigFile {
+    Tags,
+    Sort,
+    Views,
+    DoneLanes,
+}
+
+impl ConfigFile {
+    pub fn file_name(self) -> &'static str {
+        match self {
+            ConfigFile::Tags => "tags.json",
+            ConfigFile::Sort => "sort.json",
+            ConfigFile::Views => "views.json",
+            ConfigFile::DoneLanes => "done-lanes.json",
+        }
+    }
+}
+
+// A reversible change to the tasks or config directory. Applying an
+// operation returns the operation that reverts it, which is how undo
+// produces redo steps and the other way around. Paths use the frontend form.
+#[derive(Clone, Debug)]
+pub enum Operation {
+    Trash { path: String },
+    Restore { trash_id: String },
+    Rename { from: String, to: String },
+    Write { path: String, content: String },
+    // `value: None` removes the board's entry
+    SetConfig { file: ConfigFile, board: String, value: Option<Value> },
+}
+
+// Directories operations are applied to
+pub struct Context<'a> {
+    pub tasks_root: &'a Path,
+    pub config_root: &'a Path,
+    pub store: &'a ConfigStore,
+}
+
+impl Operation {
+    pub fn apply(&self, context: &Context) -> Result<Operation, CommandError> {
+        match self {
+            Operation::Trash { path } => {
+                let full_path = paths::resolve_entry(context.tasks_root, path)?;
+                let entry = trash::trash(context.tasks_root, &full_path).map_err(|e| CommandError::io(e, path))?;
+                Ok(Operation::Restore { trash_id: entry.id })
+            }
+            Operation::Restore { trash_id } => {
+                let entry = trash::restore(context.tasks_root, trash_id)?;
+                Ok(Operation::Trash { path: entry.original_path })
+            }
+            Operation::Rename { from, to } => {
+                let from_path = paths::resolve_entry(context.tasks_root, from)?;
+                let to_path = paths::resolve_entry(context.tasks_root, to)?;
+                if let Some(parent) = to_path.parent() {
+                    fs::create_dir_all(parent).map_err(|e| CommandError::io(e, to))?;
+                }
+                storage::rename_new(&from_path, &to_path).map_err(|e| CommandError::rename(e, from, to))?;
+                Ok(Operation::Rename { from: to.clone(), to: from.clone() })
+            }
+            Operation::Write { path, content } => {
+                let full_path = paths::resolve_entry(context.tasks_root, path)?;
+                let previous = fs::read_to_string(&full_path).map_err(|e| CommandError::io(e, path))?;
+                storage::write_atomic(&full_path, content.as_bytes()).map_err(|e| CommandError::io(e, path))?;
+                Ok(Operation::Write { path: path.clone(), content: previous })
+            }
+            Operation::SetConfig { file, board, value } => {
+                let config_path = paths::resolve_entry(context.config_root, file.file_name())?;
+                let previous = set_config(context.store, &config_path, board, value.clone())?;
+                Ok(Operation::SetConfig { file: *file, board: board.clone(), value: previous })
+            }
+        }
+    }
+}
+
+// Sets or removes `board` in a config file, returning its previous value
+pub fn set_config(store: &ConfigStore, config_path: &Path, board: &str, value: Option<Value>) -> Result<Option<Value>, CommandError> {
+    let mut previous = None;
+    store.update(config_path, |config| {
+        previous = match value {
+            Some(value) => config.insert(board.to_string(), value),
+            None => config.remove(board),
+        };
+    }).map_err(|e| CommandError::io(e, &config_path.file_name().unwrap_or_default().to_string_lossy()))?;
+    Ok(previous)
+}
+
+// Operations that revert one undo step, in the order they have to be applied
+struct Entry {
+    operations: Vec<Operation>,
+    recorded_at: Instant,
+    // Sealed entries are never merged with later mutations
+    sealed: bool,
+}
+
+#[derive(Default)]
+struct Stacks {
+    undo: VecDeque<Entry>,
+    redo: Vec<Entry>,
+}
+
+// Undo and redo history of the mutating commands
+#[derive(Default)]
+pub struct Journal {
+    stacks: Mutex<Stacks>,
+}
+
+impl Journal {
+    // Records the operations reverting a mutation that was just performed.
+    // Any new mutation discards the redo history.
+    pub fn record(&self, mut inverse: Vec<Operation>) {
+        if inverse.is_empty() {
+            return;
+        }
+
+        let mut stacks = self.stacks.lock().unwrap();
+        stacks.redo.clear();
+
+        let now = Instant::now();
+        if let Some(last) = stacks.undo.back_mut() {
+            if !last.sealed && now.duration_since(last.recorded_at) < COALESCE_WINDOW {
+                // Reverting the newest mutation comes first
+                inverse.append(&mut last.operations);
+                last.operations = inverse;
+                last.recorded_at = now;
+                return;
+            }
+        }
+
+        stacks.undo.push_back(Entry { operations: inverse, recorded_at: now, sealed: false });
+        if stacks.undo.len() > MAX_ENTRIES {
+            stacks.undo.pop_front();
+        }
+    }
+
+    // Reverts the latest step. Returns `false` when there is nothing to undo.
+    pub fn undo(&self, context: &Context) -> Result<bool, CommandError> {
+        let entry = {
+            let mut stacks = self.stacks.lock().unwrap();
+            let entry = stacks.undo.pop_back();
+            if let Some(previous) = stacks.undo.back_mut() {
+                previous.sealed = true;
+            }
+            match entry {
+                Some(entry) => entry,
+                None => return Ok(false),
+            }
+        };
+
+        let redo = match apply_all(&entry.operations, context) {
+            Ok(redo) => redo,
+            Err(failure) => {
+                // Nothing changed, so the step can be undone again later
+                if failure.reverted {
+                    self.stacks.lock().unwrap().undo.push_back(Entry { sealed: true, ..entry });
+                }
+                return Err(failure.error);
+            }
+        };
+        self.stacks.lock().unwrap().redo.push(Entry { operations: redo, recorded_at: Instant::now(), sealed: true });
+        Ok(true)
+    }
+
+    // Reapplies the latest undone step. Returns `false` when there is nothing to redo.
+    pub fn redo(&self, context: &Context) -> Result<bool, CommandError> {
+        let entry = match self.stacks.lock().unwrap().redo.pop() {
+            Some(entry) => entry,
+            None => return Ok(false),
+        };
+
+        let undo = match apply_all(&entry.operations, context) {
+            Ok(undo) => undo,
+            Err(failure) => {
+                if failure.reverted {
+                    self.stacks.lock().unwrap().redo.push(entry);
+                }
+                return Err(failure.error);
+            }
+        };
+        self.stacks.lock().unwrap().undo.push_back(Entry { operations: undo, recorded_at: Instant::now(), sealed: true });
+        Ok(true)
+    }
+}
+
+// An undo or redo step that could not be applied
+struct Failure {
+    error: CommandError,
+    // Whether the operations applied before the failing one were reverted,
+    // leaving the files as they were before the step
+    reverted: bool,
+}
+
+// Applies `operations` in order and returns their inverses in the order that
+// reverts them. When one fails, the ones already applied are reverted so the
+// step is applied either in full or not at all.
+fn apply_all(operations: &[Operation], context: &Context) -> Result<Vec<Operation>, Failure> {
+    let mut inverse = Vec::with_capacity(operations.len());
+    for operation in operations {
+        match operation.apply(context) {
+            Ok(operation) => inverse.push(operation),
+            Err(mut error) => {
+                let mut reverted = true;
+                for operation in inverse.iter().rev() {
+                    if let Err(e) = operation.apply(context) {
+                        eprintln!("Failed to revert a partly applied step: {}", e);
+                        error.message = format!("{} (the step was only partly applied)", error.message);
+                        reverted = false;
+                        break;
+                    }
+                }
+                return Err(Failure { error, reverted });
+            }
+        }
+    }
+    inverse.reverse();
+    Ok(inverse)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    #[test]
+    fn reverts_a_step_that_fails_part_way() {
+        let dir = TempDir::new("journal-revert");
+        let card = dir.write("tasks/board/lane/card.md", "edited");
+        let (tasks_root, config_root) = (dir.path().join("tasks"), dir.path().join("config"));
+        let store = ConfigStore::default();
+        let context = Context { tasks_root: &tasks_root, config_root: &config_root, store: &store };
+
+        let journal = Journal::default();
+        journal.record(vec![
+            Operation::Write { path: "/board/lane/card.md".to_string(), content: "original".to_string() },
+            Operation::Rename { from: "/board/lane/moved.md".to_string(), to: "/board/lane/old.md".to_string() },
+        ]);
+
+        // The rename fails, so the write before it is reverted and the step kept
+        assert!(journal.undo(&context).is_err());
+        assert_eq!(fs::read_to_string(&card).unwrap(), "edited");
+
+        fs::write(tasks_root.join("board/lane/moved.md"), "moved").unwrap();
+        assert!(journal.undo(&context).unwrap());
+        assert_eq!(fs::read_to_string(&card).unwrap(), "original");
+        assert_eq!(fs::read_to_string(tasks_root.join("board/lane/old.md")).unwrap(), "moved");
+
+        assert!(journal.redo(&context).unwrap());
+        assert_eq!(fs::read_to_string(&card).unwrap(), "edited");
+        assert!(tasks_root.join("board/lane/moved.md").is_file());
+        assert!(!journal.redo(&context).unwrap());
+    }
+}
@@ -1,10 +1,40 @@
-use tauri::{command, State, AppHandle, Emitter};
+use tauri::{command, State, AppHandle, Manager, Window, WindowEvent};
+use tauri::ipc::{InvokeBody, Request};
 use std::fs;
 use std::path::Path;
 use serde_json::{Value, Map};
-use uuid::Uuid;
+use card::Card;
+use config::ConfigStore;
+use error::{CommandError, ErrorCode};
+use history::History;
+use journal::{ConfigFile, Journal, Operation};
+use query::{QueryResult, SortBy, SortDirection};
+use search::SearchIndex;
+use storage::OnConflict;
+use views::SavedView;
 use std::sync::Mutex;
-use std::time::SystemTime;
+use std::time::Duration;
+
+mod agenda;
+mod boards;
+mod card;
+mod config;
+mod due;
+mod error;
+mod frontmatter;
+mod history;
+mod images;
+mod journal;
+mod paths;
+mod query;
+mod search;
+mod storage;
+mod thumbnails;
+mod trash;
+mod views;
+mod watcher;
+#[cfg(test)]
+mod test_support;
 
 // App state for configuration
 #[derive(Default)]
@@ -12,112 +42,105 @@ pub struct AppState {
     config_dir: Mutex<String>,
     tasks_dir: Mutex<String>,
     title: Mutex<String>,
+    // Days deleted cards and lanes are kept in the trash, 0 keeps them forever
+    trash_retention_days: u64,
+    // Largest image that can be uploaded in bytes, 0 allows any size
+    max_image_size: u64,
+    thumbnails: thumbnails::Config,
 }
 
 // File watcher state
 #[derive(Default)]
 pub struct WatchState {
-    watching: Mutex<bool>,
+    watchers: Mutex<watcher::Watchers>,
+    own_writes: watcher::OwnWrites,
 }
 
 #[command]
-async fn get_tags(path: String, state: State<'_, AppState>) -> Result<Value, String> {
+async fn get_tags(path: String, state: State<'_, AppState>, store: State<'_, ConfigStore>) -> Result<Value, CommandError> {
     let config_dir = state.config_dir.lock().unwrap().clone();
-    let tags_path = format!("{}/tags.json", config_dir);
+    let tags_path = paths::resolve_entry(Path::new(&config_dir), ConfigFile::Tags.file_name())?;
 
-    let tags = fs::read_to_string(&tags_path)
-        .map(|content| serde_json::from_str::<Value>(&content).unwrap_or(Value::Object(Map::new())))
-        .unwrap_or(Value::Object(Map::new()));
+    let tags = store.read(&tags_path).map_err(|e| CommandError::io(e, ConfigFile::Tags.file_name()))?;
 
-    if let Value::Object(obj) = tags {
-        Ok(obj.get(&path).cloned().unwrap_or(Value::Object(Map::new())))
-    } else {
-        Ok(Value::Object(Map::new()))
-    }
+    Ok(tags.get(&path).cloned().unwrap_or(Value::Object(Map::new())))
 }
 
 #[command]
-async fn update_tag_background_color(path: String, colors: Value, state: State<'_, AppState>) -> Result<(), String> {
+async fn update_tag_background_color(path: String, colors: Value, state: State<'_, AppState>, store: State<'_, ConfigStore>, journal: State<'_, Journal>, watch_state: State<'_, WatchState>) -> Result<(), CommandError> {
     let config_dir = state.config_dir.lock().unwrap().clone();
-    let tags_path = format!("{}/tags.json", config_dir);
-
-    let mut tags = fs::read_to_string(&tags_path)
-        .map(|content| serde_json::from_str::<Value>(&content).unwrap_or(Value::Object(Map::new())))
-        .unwrap_or(Value::Object(Map::new()));
+    let tags_path = paths::resolve_entry(Path::new(&config_dir), ConfigFile::Tags.file_name())?;
 
-    if let Value::Object(ref mut obj) = tags {
-        obj.insert(path, colors);
+    watch_state.own_writes.record(&tags_path);
+    let previous = journal::set_config(&store, &tags_path, &path, Some(colors.clone()))?;
+    if previous.as_ref() != Some(&colors) {
+        journal.record(vec![Operation::SetConfig { file: ConfigFile::Tags, board: path, value: previous }]);
     }
 
-    fs::create_dir_all(&config_dir).map_err(|e| e.to_string())?;
-    fs::write(&tags_path, serde_json::to_string(&tags).map_err(|e| e.to_string())?)
-        .map_err(|e| e.to_string())?;
-
     Ok(())
 }
 
 #[command]
-async fn get_title(state: State<'_, AppState>) -> Result<String, String> {
+async fn get_title(state: State<'_, AppState>) -> Result<String, CommandError> {
     Ok(state.title.lock().unwrap().clone())
 }
 
 #[command]
-async fn get_resource(path: String, state: State<'_, AppState>) -> Result<Value, String> {
+async fn get_resource(path: String, state: State<'_, AppState>) -> Result<Value, CommandError> {
     let tasks_dir = state.tasks_dir.lock().unwrap().clone();
-    let full_path = format!("{}/{}", tasks_dir, path);
+    paths::check_visible(&path)?;
+    let full_path = paths::resolve(Path::new(&tasks_dir), &path)?;
 
     // Create directory if it doesn't exist
-    if !Path::new(&full_path).exists() {
-        fs::create_dir_all(&full_path).map_err(|e| e.to_string())?;
+    if !full_path.exists() {
+        fs::create_dir_all(&full_path).map_err(|e| CommandError::io(e, &path))?;
         return Ok(Value::Array(vec![]));
     }
 
-    let entries = fs::read_dir(&full_path).map_err(|e| e.to_string())?;
+    let lanes = read_lanes(&full_path, &path)?
+        .into_iter()
+        .map(|(lane_name, files)| serde_json::json!({
+            "name": lane_name,
+            "files": files
+        }))
+        .collect();
+
+    Ok(Value::Array(lanes))
+}
+
+// Lanes of the board at `board_path` with their cards, `board` being its frontend path
+fn read_lanes(board_path: &Path, board: &str) -> Result<Vec<(String, Vec<Card>)>, CommandError> {
+    let entries = fs::read_dir(board_path).map_err(|e| CommandError::io(e, board))?;
     let mut lanes = Vec::new();
 
     for entry in entries {
-        let entry = entry.map_err(|e| e.to_string())?;
+        let entry = entry.map_err(|e| CommandError::io(e, board))?;
         let entry_path = entry.path();
 
         if entry_path.is_dir() && !entry.file_name().to_string_lossy().starts_with('.') {
             let lane_name = entry.file_name().to_string_lossy().to_string();
-            let lane_path = entry_path.to_string_lossy().to_string();
-
-            let files = get_lane_files(&lane_path)?;
-
-            lanes.push(serde_json::json!({
-                "name": lane_name,
-                "files": files
-            }));
+            let files = get_lane_files(&entry_path, &format!("{}/{}", board, lane_name))?;
+            lanes.push((lane_name, files));
         }
     }
 
-    Ok(Value::Array(lanes))
+    Ok(lanes)
 }
 
-fn get_lane_files(lane_path: &str) -> Result<Vec<Value>, String> {
-    let entries = fs::read_dir(lane_path).map_err(|e| e.to_string())?;
+// `lane` is the frontend path of `lane_path`, used in error messages
+fn get_lane_files(lane_path: &Path, lane: &str) -> Result<Vec<Card>, CommandError> {
+    let entries = fs::read_dir(lane_path).map_err(|e| CommandError::io(e, lane))?;
     let mut files = Vec::new();
 
     for entry in entries {
-        let entry = entry.map_err(|e| e.to_string())?;
+        let entry = entry.map_err(|e| CommandError::io(e, lane))?;
         let file_path = entry.path();
 
         if let Some(file_name) = file_path.file_name() {
             let file_name_str = file_name.to_string_lossy();
 
             if file_name_str.ends_with(".md") && !file_name_str.starts_with('.') {
-                let content = fs::read_to_string(&file_path).map_err(|e| e.to_string())?;
-                let metadata = fs::metadata(&file_path).map_err(|e| e.to_string())?;
-
-                let name = file_name_str.strip_suffix(".md").unwrap_or(&file_name_str);
-
-                files.push(serde_json::json!({
-                    "name": name,
-                    "content": content,
-                    "lastUpdated": metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
-                    "createdAt": metadata.created().unwrap_or(SystemTime::UNIX_EPOCH)
-                }));
+                files.push(Card::read(&file_path).map_err(|e| CommandError::io(e, &format!("{}/{}", lane, file_name_str)))?);
             }
         }
     }
@@ -125,183 +148,657 @@ fn get_lane_files(lane_path: &str) -> Result<Vec<Value>, String> {
     Ok(files)
 }
 
+// Cards of the board at `path` matching `query` (see `query::Expr`), sorted
+// by `sort` and cut to `limit` cards
 #[command]
-async fn create_resource(path: String, is_file: Option<bool>, content: Option<String>, state: State<'_, AppState>) -> Result<(), String> {
+async fn query_cards(path: String, query: String, sort: Option<SortBy>, direction: Option<SortDirection>, limit: Option<usize>, state: State<'_, AppState>) -> Result<Vec<QueryResult>, CommandError> {
     let tasks_dir = state.tasks_dir.lock().unwrap().clone();
-    let full_path = format!("{}/{}", tasks_dir, path);
+    paths::check_visible(&path)?;
+    let full_path = paths::resolve(Path::new(&tasks_dir), &path)?;
+    let filter = query::parse(&query)?;
+
+    let mut results: Vec<QueryResult> = read_lanes(&full_path, &path)?
+        .into_iter()
+        .flat_map(|(lane, files)| files.into_iter().map(move |card| (lane.clone(), card)))
+        .filter(|(lane, card)| filter.as_ref().is_none_or(|filter| filter.matches(card, lane)))
+        .map(|(lane, card)| QueryResult { path: format!("{}/{}/{}.md", path, lane, card.name), lane, card })
+        .collect();
+
+    if let Some(sort) = sort {
+        query::sort(&mut results, sort, direction.unwrap_or(SortDirection::Asc));
+    }
+    if let Some(limit) = limit {
+        results.truncate(limit);
+    }
 
-    if is_file.unwrap_or(false) {
-        if let Some(parent) = Path::new(&full_path).parent() {
-            fs::create_dir_all(parent).map_err(|e| e.to_string())?;
-        }
-        fs::write(&full_path, content.unwrap_or_default()).map_err(|e| e.to_string())?;
-    } else {
-        fs::create_dir_all(&full_path).map_err(|e| e.to_string())?;
+    Ok(results)
+}
+
+// Every board under the tasks directory as a tree, with the card and
+// overdue counts of their lanes
+#[command]
+async fn get_boards(state: State<'_, AppState>) -> Result<boards::BoardSummary, CommandError> {
+    let tasks_dir = state.tasks_dir.lock().unwrap().clone();
+    let tasks_root = paths::resolve(Path::new(&tasks_dir), "")?;
+
+    boards::summarize(&tasks_root, "", due::today())
+}
+
+// Single card lookup, used to patch the board after a `files-changed` event
+#[command]
+async fn get_card(path: String, state: State<'_, AppState>) -> Result<Card, CommandError> {
+    let tasks_dir = state.tasks_dir.lock().unwrap().clone();
+    paths::check_visible(&path)?;
+    let full_path = paths::resolve_entry(Path::new(&tasks_dir), &path)?;
+
+    Card::read(&full_path).map_err(|e| CommandError::io(e, &path))
+}
+
+// Creates a card or lane and returns its path, which differs from `path`
+// when `on_conflict` is `suffix` and the name was taken
+#[allow(clippy::too_many_arguments)]
+#[command]
+async fn create_resource(path: String, is_file: Option<bool>, content: Option<String>, on_conflict: Option<OnConflict>, state: State<'_, AppState>, journal: State<'_, Journal>, history: State<'_, History>, watch_state: State<'_, WatchState>) -> Result<String, CommandError> {
+    let tasks_dir = state.tasks_dir.lock().unwrap().clone();
+    paths::check_visible(&path)?;
+    let tasks_root = paths::resolve(Path::new(&tasks_dir), "")?;
+    let full_path = paths::resolve_entry(&tasks_root, &path)?;
+    let relative_path = paths::to_relative(&tasks_root, &full_path);
+    paths::validate_name(&relative_path)?;
+
+    let is_file = is_file.unwrap_or(false);
+    // Relative due dates are pinned to the day the card is written, as on updates
+    let content = due::normalize_content(&content.unwrap_or_default(), due::today());
+
+    if let Some(parent) = full_path.parent() {
+        fs::create_dir_all(parent).map_err(|e| CommandError::io(e, &relative_path))?;
     }
+    let created_path = storage::claim_name(&full_path, on_conflict.unwrap_or_default(), |candidate| {
+        watch_state.own_writes.record(candidate);
+        if is_file {
+            storage::create_new(candidate, content.as_bytes())
+        } else {
+            fs::create_dir(candidate)
+        }
+    }).map_err(|e| CommandError::io(e, &relative_path))?;
 
-    Ok(())
+    let created = paths::to_relative(&tasks_root, &created_path);
+    journal.record(vec![Operation::Trash { path: created.clone() }]);
+    history.record(history::describe_create(&created, is_file));
+    Ok(created)
+}
+
+#[derive(serde::Serialize)]
+struct UpdatedResource {
+    // Where the card or lane ended up, see `OnConflict::Suffix`
+    path: String,
+    // The card, when its content was written
+    card: Option<Card>,
 }
 
+// Tauri injects state as arguments, so commands needing several managed states grow long
+#[allow(clippy::too_many_arguments)]
 #[command]
-async fn update_resource(path: String, new_path: Option<String>, content: Option<String>, state: State<'_, AppState>) -> Result<(), String> {
+async fn update_resource(path: String, new_path: Option<String>, content: Option<String>, expected_version: Option<String>, on_conflict: Option<OnConflict>, normalize_due: Option<bool>, state: State<'_, AppState>, journal: State<'_, Journal>, history: State<'_, History>, watch_state: State<'_, WatchState>) -> Result<UpdatedResource, CommandError> {
     let tasks_dir = state.tasks_dir.lock().unwrap().clone();
-    let old_full_path = format!("{}/{}", tasks_dir, path);
+    paths::check_visible(&path)?;
+    let tasks_root = paths::resolve(Path::new(&tasks_dir), "")?;
+    let old_full_path = paths::resolve_entry(&tasks_root, &path)?;
 
-    let new_path_clean = new_path.unwrap_or(path.clone())
-        .chars()
-        .map(|c| if "<>:\"/\\|?*".contains(c) { ' ' } else { c })
-        .collect::<String>();
+    let new_path_clean = paths::sanitize(&new_path.unwrap_or(path.clone()));
+    paths::check_visible(&new_path_clean)?;
+    let mut new_full_path = paths::resolve_entry(&tasks_root, &new_path_clean)?;
+    if old_full_path != new_full_path {
+        paths::validate_name(&new_path_clean)?;
+    }
+    let mut inverse = Vec::new();
+
+    if let Some(expected_version) = expected_version.filter(|_| content.is_some()) {
+        check_version(&path, &old_full_path, expected_version)?;
+    }
 
-    let new_full_path = format!("{}/{}", tasks_dir, new_path_clean);
+    watch_state.own_writes.record(&old_full_path);
 
     if old_full_path != new_full_path {
-        if let Some(parent) = Path::new(&new_full_path).parent() {
-            fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        if let Some(parent) = new_full_path.parent() {
+            fs::create_dir_all(parent).map_err(|e| CommandError::io(e, &new_path_clean))?;
         }
-        fs::rename(&old_full_path, &new_full_path).map_err(|e| e.to_string())?;
+        new_full_path = storage::claim_name(&new_full_path, on_conflict.unwrap_or_default(), |candidate| {
+            watch_state.own_writes.record(candidate);
+            storage::rename_new(&old_full_path, candidate)
+        }).map_err(|e| CommandError::rename(e, &path, &new_path_clean))?;
+        inverse.push(Operation::Rename {
+            from: paths::to_relative(&tasks_root, &new_full_path),
+            to: paths::to_relative(&tasks_root, &old_full_path),
+        });
     }
 
-    if let Some(new_content) = content {
-        let metadata = fs::metadata(&new_full_path).map_err(|e| e.to_string())?;
+    let mut card = None;
+    if let Some(mut new_content) = content {
+        if normalize_due.unwrap_or(true) {
+            new_content = due::normalize_content(&new_content, due::today());
+        }
+        let metadata = fs::metadata(&new_full_path).map_err(|e| CommandError::io(e, &new_path_clean))?;
         if metadata.is_file() {
-            fs::write(&new_full_path, new_content).map_err(|e| e.to_string())?;
+            let previous = fs::read_to_string(&new_full_path).map_err(|e| CommandError::io(e, &new_path_clean))?;
+            storage::write_atomic(&new_full_path, new_content.as_bytes()).map_err(|e| CommandError::io(e, &new_path_clean))?;
+            card = Some(Card::read(&new_full_path).map_err(|e| CommandError::io(e, &new_path_clean))?);
+
+            if previous != new_content {
+                // Reverted before renaming back
+                inverse.insert(0, Operation::Write { path: paths::to_relative(&tasks_root, &new_full_path), content: previous });
+            }
         }
     }
 
+    if !inverse.is_empty() {
+        history.record(history::describe_update(
+            &paths::to_relative(&tasks_root, &old_full_path),
+            &paths::to_relative(&tasks_root, &new_full_path),
+            new_full_path.is_file(),
+        ));
+    }
+    journal.record(inverse);
+    Ok(UpdatedResource { path: paths::to_relative(&tasks_root, &new_full_path), card })
+}
+
+fn check_version(path: &str, full_path: &Path, expected_version: String) -> Result<(), CommandError> {
+    let current_content = fs::read_to_string(full_path).map_err(|e| CommandError::io(e, path))?;
+    let current_version = card::version(current_content.as_bytes());
+    if current_version != expected_version {
+        return Err(CommandError::conflict(path, expected_version, current_version, current_content));
+    }
     Ok(())
 }
 
+// Sets one frontmatter field of a card, or removes it when `value` is null,
+// without touching the rest of the file
+#[allow(clippy::too_many_arguments)]
 #[command]
-async fn delete_resource(path: String, state: State<'_, AppState>) -> Result<(), String> {
+async fn update_card_field(path: String, field: String, value: Option<Value>, expected_version: Option<String>, normalize_due: Option<bool>, state: State<'_, AppState>, journal: State<'_, Journal>, history: State<'_, History>, watch_state: State<'_, WatchState>) -> Result<Card, CommandError> {
     let tasks_dir = state.tasks_dir.lock().unwrap().clone();
-    let full_path = format!("{}/{}", tasks_dir, path);
+    paths::check_visible(&path)?;
+    let tasks_root = paths::resolve(Path::new(&tasks_dir), "")?;
+    let full_path = paths::resolve_entry(&tasks_root, &path)?;
+    let relative_path = paths::to_relative(&tasks_root, &full_path);
 
-    if Path::new(&full_path).is_dir() {
-        fs::remove_dir_all(&full_path).map_err(|e| e.to_string())?;
-    } else {
-        fs::remove_file(&full_path).map_err(|e| e.to_string())?;
+    if !frontmatter::is_valid_key(&field) {
+        return Err(CommandError::new(ErrorCode::InvalidName, format!("\"{}\" is not a valid field name", field)).at(&path));
+    }
+    if let Some(expected_version) = expected_version {
+        check_version(&path, &full_path, expected_version)?;
     }
 
-    Ok(())
+    let previous = fs::read_to_string(&full_path).map_err(|e| CommandError::io(e, &path))?;
+    let mut content = frontmatter::set_field(&previous, &field, value.as_ref())
+        .map_err(|e| CommandError::new(ErrorCode::InvalidData, format!("Could not update {}: {}", field, e)).at(&path))?;
+    if normalize_due.unwrap_or(true) {
+        content = due::normalize_content(&content, due::today());
+    }
+
+    if content != previous {
+        watch_state.own_writes.record(&full_path);
+        storage::write_atomic(&full_path, content.as_bytes()).map_err(|e| CommandError::io(e, &path))?;
+        history.record(history::describe_update(&relative_path, &relative_path, true));
+        journal.record(vec![Operation::Write { path: relative_path, content: previous }]);
+    }
+
+    Card::read(&full_path).map_err(|e| CommandError::io(e, &path))
+}
+
+#[command]
+async fn delete_resource(path: String, state: State<'_, AppState>, journal: State<'_, Journal>, history: State<'_, History>, watch_state: State<'_, WatchState>) -> Result<(), CommandError> {
+    let tasks_dir = state.tasks_dir.lock().unwrap().clone();
+    paths::check_visible(&path)?;
+    let tasks_root = paths::resolve(Path::new(&tasks_dir), "")?;
+    let full_path = paths::resolve_entry(&tasks_root, &path)?;
+    watch_state.own_writes.record(&full_path);
+
+    // Symlinks are moved as links, never by following them into their target
+    let entry = trash::trash(&tasks_root, &full_path).map_err(|e| CommandError::io(e, &path))?;
+    history.record(history::describe_delete(&entry.original_path, !entry.is_dir));
+    journal.record(vec![Operation::Restore { trash_id: entry.id }]);
+
+    purge_expired_trash(&tasks_root, &state)
+}
+
+fn purge_expired_trash(tasks_root: &Path, state: &AppState) -> Result<(), CommandError> {
+    if state.trash_retention_days == 0 {
+        return Ok(());
+    }
+
+    let retention = Duration::from_secs(state.trash_retention_days * 24 * 60 * 60);
+    Ok(trash::purge_expired(tasks_root, retention)?)
+}
+
+#[command]
+async fn list_trash(state: State<'_, AppState>) -> Result<Vec<trash::TrashEntry>, CommandError> {
+    let tasks_dir = state.tasks_dir.lock().unwrap().clone();
+    let tasks_root = paths::resolve(Path::new(&tasks_dir), "")?;
+
+    purge_expired_trash(&tasks_root, &state)?;
+    Ok(trash::list(&tasks_root)?)
+}
+
+#[command]
+async fn restore_trash(id: String, state: State<'_, AppState>, journal: State<'_, Journal>, history: State<'_, History>) -> Result<trash::TrashEntry, CommandError> {
+    let tasks_dir = state.tasks_dir.lock().unwrap().clone();
+    let tasks_root = paths::resolve(Path::new(&tasks_dir), "")?;
+
+    let entry = trash::restore(&tasks_root, &id)?;
+    history.record(history::describe_restore(&entry.original_path, !entry.is_dir));
+    journal.record(vec![Operation::Trash { path: entry.original_path.clone() }]);
+    Ok(entry)
+}
+
+// Permanently deletes one trash entry, or everything in the trash when `id` is omitted
+#[command]
+async fn purge_trash(id: Option<String>, state: State<'_, AppState>) -> Result<(), CommandError> {
+    let tasks_dir = state.tasks_dir.lock().unwrap().clone();
+    let tasks_root = paths::resolve(Path::new(&tasks_dir), "")?;
+
+    trash::purge(&tasks_root, id.as_deref())
 }
 
+// The image is the raw request body, not a JSON array, and its original file
+// name the URI-encoded `X-Filename` header
 #[command]
-async fn upload_image(file_data: Vec<u8>, filename: String, state: State<'_, AppState>) -> Result<String, String> {
+async fn upload_image(request: Request<'_>, state: State<'_, AppState>, thumbnail_queue: State<'_, thumbnails::Queue>) -> Result<String, CommandError> {
     let config_dir = state.config_dir.lock().unwrap().clone();
     let images_dir = format!("{}/images", config_dir);
+    let file_data = match request.body() {
+        InvokeBody::Raw(data) => data,
+        InvokeBody::Json(_) => return Err(CommandError::new(ErrorCode::InvalidData, "The image must be sent as raw bytes")),
+    };
+    let filename = request.headers().get("x-filename")
+        .and_then(|value| value.to_str().ok())
+        .and_then(images::percent_decode)
+        .unwrap_or_else(|| "image".to_string());
+
+    // The extension is taken from the content, the file name is only trusted for messages
+    let format = images::validate(file_data, &filename, state.max_image_size)?;
+
+    fs::create_dir_all(&images_dir).map_err(|e| CommandError::io(e, &images_dir))?;
+
+    let image_name = images::content_name(file_data, format.extension());
+    let image_path = paths::resolve_entry(Path::new(&images_dir), &image_name)?;
+
+    // The same content always gets the same name, so an existing file is that image
+    if !image_path.exists() {
+        storage::write_atomic(&image_path, file_data).map_err(|e| CommandError::io(e, &image_name))?;
+        thumbnail_queue.prepare(Path::new(&images_dir).to_path_buf(), image_name.clone(), format);
+    }
 
-    fs::create_dir_all(&images_dir).map_err(|e| e.to_string())?;
+    Ok(image_name)
+}
 
-    let extension = filename.split('.').last().unwrap_or("png");
-    let image_name = format!("{}.{}", Uuid::new_v4(), extension);
-    let image_path = format!("{}/{}", images_dir, image_name);
+#[command]
+async fn update_sort(path: String, sort_data: Value, state: State<'_, AppState>, store: State<'_, ConfigStore>, journal: State<'_, Journal>, watch_state: State<'_, WatchState>) -> Result<(), CommandError> {
+    let config_dir = state.config_dir.lock().unwrap().clone();
+    let sort_path = paths::resolve_entry(Path::new(&config_dir), ConfigFile::Sort.file_name())?;
 
-    fs::write(&image_path, file_data).map_err(|e| e.to_string())?;
+    watch_state.own_writes.record(&sort_path);
+    let previous = journal::set_config(&store, &sort_path, &path, Some(sort_data.clone()))?;
+    // The frontend resends the sort on every render, only actual changes are undoable
+    if previous.as_ref() != Some(&sort_data) {
+        journal.record(vec![Operation::SetConfig { file: ConfigFile::Sort, board: path, value: previous }]);
+    }
 
-    Ok(image_name)
+    Ok(())
+}
+
+#[command]
+async fn get_sort(path: String, state: State<'_, AppState>, store: State<'_, ConfigStore>) -> Result<Value, CommandError> {
+    let config_dir = state.config_dir.lock().unwrap().clone();
+    let sort_path = paths::resolve_entry(Path::new(&config_dir), ConfigFile::Sort.file_name())?;
+
+    let sort = store.read(&sort_path).map_err(|e| CommandError::io(e, ConfigFile::Sort.file_name()))?;
+
+    Ok(sort.get(&path).cloned().unwrap_or(Value::Object(Map::new())))
 }
 
+// Lanes of the board at `path` holding finished cards, left out of the agenda
 #[command]
-async fn update_sort(path: String, sort_data: Value, state: State<'_, AppState>) -> Result<(), String> {
+async fn get_done_lanes(path: String, state: State<'_, AppState>, store: State<'_, ConfigStore>) -> Result<Vec<String>, CommandError> {
     let config_dir = state.config_dir.lock().unwrap().clone();
-    let sort_path = format!("{}/sort.json", config_dir);
+    let done_lanes_path = paths::resolve_entry(Path::new(&config_dir), ConfigFile::DoneLanes.file_name())?;
 
-    let mut current_sort = fs::read_to_string(&sort_path)
-        .map(|content| serde_json::from_str::<Value>(&content).unwrap_or(Value::Object(Map::new())))
-        .unwrap_or(Value::Object(Map::new()));
+    let done_lanes = store.read(&done_lanes_path).map_err(|e| CommandError::io(e, ConfigFile::DoneLanes.file_name()))?;
 
-    if let Value::Object(ref mut obj) = current_sort {
-        obj.insert(path, sort_data);
+    Ok(done_lanes.get(&path).and_then(|lanes| serde_json::from_value(lanes.clone()).ok()).unwrap_or_default())
+}
+
+#[command]
+async fn update_done_lanes(path: String, lanes: Vec<String>, state: State<'_, AppState>, store: State<'_, ConfigStore>, journal: State<'_, Journal>, watch_state: State<'_, WatchState>) -> Result<(), CommandError> {
+    let config_dir = state.config_dir.lock().unwrap().clone();
+    let done_lanes_path = paths::resolve_entry(Path::new(&config_dir), ConfigFile::DoneLanes.file_name())?;
+
+    let value = if lanes.is_empty() { None } else { Some(Value::from(lanes)) };
+    watch_state.own_writes.record(&done_lanes_path);
+    let previous = journal::set_config(&store, &done_lanes_path, &path, value.clone())?;
+    if previous != value {
+        journal.record(vec![Operation::SetConfig { file: ConfigFile::DoneLanes, board: path, value: previous }]);
     }
 
-    fs::create_dir_all(&config_dir).map_err(|e| e.to_string())?;
-    fs::write(&sort_path, serde_json::to_string(&current_sort).map_err(|e| e.to_string())?)
-        .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+// Cards of every board grouped into overdue, today, this week and later.
+// Cards in the done lanes of their board are left out unless `exclude_done`
+// is false.
+#[command]
+async fn get_agenda(exclude_done: Option<bool>, state: State<'_, AppState>, store: State<'_, ConfigStore>) -> Result<agenda::Agenda, CommandError> {
+    let tasks_dir = state.tasks_dir.lock().unwrap().clone();
+    let config_dir = state.config_dir.lock().unwrap().clone();
+    let tasks_root = paths::resolve(Path::new(&tasks_dir), "")?;
+    let done_lanes_path = paths::resolve_entry(Path::new(&config_dir), ConfigFile::DoneLanes.file_name())?;
+
+    let done_lanes = match exclude_done {
+        Some(false) => None,
+        _ => Some(store.read(&done_lanes_path).map_err(|e| CommandError::io(e, ConfigFile::DoneLanes.file_name()))?),
+    };
+
+    agenda::collect(&tasks_root, due::today(), done_lanes.as_ref())
+}
+
+#[command]
+async fn list_views(path: String, state: State<'_, AppState>, store: State<'_, ConfigStore>) -> Result<Vec<SavedView>, CommandError> {
+    let config_dir = state.config_dir.lock().unwrap().clone();
+    let views_path = paths::resolve_entry(Path::new(&config_dir), ConfigFile::Views.file_name())?;
+
+    views::list(&store, &views_path, &path)
+}
+
+#[command]
+async fn create_view(path: String, view: SavedView, state: State<'_, AppState>, store: State<'_, ConfigStore>, journal: State<'_, Journal>, watch_state: State<'_, WatchState>) -> Result<(), CommandError> {
+    update_views(&path, &state, &store, &journal, &watch_state, |views| views::create(views, view))
+}
+
+// Replaces the view called `name`, renaming it when `view.name` differs
+#[command]
+async fn update_view(path: String, name: String, view: SavedView, state: State<'_, AppState>, store: State<'_, ConfigStore>, journal: State<'_, Journal>, watch_state: State<'_, WatchState>) -> Result<(), CommandError> {
+    update_views(&path, &state, &store, &journal, &watch_state, |views| views::replace(views, &name, view))
+}
+
+#[command]
+async fn delete_view(path: String, name: String, state: State<'_, AppState>, store: State<'_, ConfigStore>, journal: State<'_, Journal>, watch_state: State<'_, WatchState>) -> Result<(), CommandError> {
+    update_views(&path, &state, &store, &journal, &watch_state, |views| views::delete(views, &name))
+}
+
+fn update_views<F>(board: &str, state: &AppState, store: &ConfigStore, journal: &Journal, watch_state: &WatchState, change: F) -> Result<(), CommandError>
+where
+    F: FnOnce(&mut Vec<SavedView>) -> Result<(), CommandError>,
+{
+    let config_dir = state.config_dir.lock().unwrap().clone();
+    let views_path = paths::resolve_entry(Path::new(&config_dir), ConfigFile::Views.file_name())?;
+
+    watch_state.own_writes.record(&views_path);
+    let previous = views::update(store, &views_path, board, change)?;
+    journal.record(vec![Operation::SetConfig { file: ConfigFile::Views, board: board.to_string(), value: previous }]);
 
     Ok(())
 }
 
+// Renames the images uploaded before they were named by content, rewriting
+// the cards referring to them
 #[command]
-async fn get_sort(path: String, state: State<'_, AppState>) -> Result<Value, String> {
+async fn migrate_images(state: State<'_, AppState>, history: State<'_, History>) -> Result<images::Migration, CommandError> {
+    let tasks_dir = state.tasks_dir.lock().unwrap().clone();
     let config_dir = state.config_dir.lock().unwrap().clone();
-    let sort_path = format!("{}/sort.json", config_dir);
+    let tasks_root = paths::resolve(Path::new(&tasks_dir), "")?;
+    let images_dir = format!("{}/images", config_dir);
 
-    let sort = fs::read_to_string(&sort_path)
-        .map(|content| serde_json::from_str::<Value>(&content).unwrap_or(Value::Object(Map::new())))
-        .unwrap_or(Value::Object(Map::new()));
+    let migration = images::migrate(Path::new(&images_dir), &tasks_root)
+        .map_err(|e| CommandError::new(ErrorCode::Io, format!("Could not migrate images: {}", e)))?;
+    if !migration.updated_cards.is_empty() {
+        history.record(format!("Rename {} images after their content", migration.renamed.len()));
+    }
 
-    if let Value::Object(obj) = sort {
-        Ok(obj.get(&path).cloned().unwrap_or(Value::Object(Map::new())))
-    } else {
-        Ok(Value::Object(Map::new()))
+    Ok(migration)
+}
+
+// Uploaded images no card refers to, which are removed unless `dry_run` is set
+#[command]
+async fn clean_images(dry_run: bool, state: State<'_, AppState>) -> Result<images::Cleanup, CommandError> {
+    let tasks_dir = state.tasks_dir.lock().unwrap().clone();
+    let config_dir = state.config_dir.lock().unwrap().clone();
+    let tasks_root = paths::resolve(Path::new(&tasks_dir), "")?;
+    let images_dir = format!("{}/images", config_dir);
+
+    images::clean(Path::new(&images_dir), &tasks_root, dry_run)
+        .map_err(|e| CommandError::new(ErrorCode::Io, format!("Could not clean up unused images: {}", e)))
+}
+
+#[command]
+async fn undo(state: State<'_, AppState>, store: State<'_, ConfigStore>, journal: State<'_, Journal>, history: State<'_, History>) -> Result<bool, CommandError> {
+    let tasks_dir = state.tasks_dir.lock().unwrap().clone();
+    let config_dir = state.config_dir.lock().unwrap().clone();
+    let tasks_root = paths::resolve(Path::new(&tasks_dir), "")?;
+    let config_root = paths::resolve(Path::new(&config_dir), "")?;
+
+    let changed = journal.undo(&journal::Context { tasks_root: &tasks_root, config_root: &config_root, store: &store })?;
+    if changed {
+        history.record("Undo last change".to_string());
     }
+    Ok(changed)
 }
 
 #[command]
-async fn get_image(filename: String, state: State<'_, AppState>) -> Result<Vec<u8>, String> {
+async fn redo(state: State<'_, AppState>, store: State<'_, ConfigStore>, journal: State<'_, Journal>, history: State<'_, History>) -> Result<bool, CommandError> {
+    let tasks_dir = state.tasks_dir.lock().unwrap().clone();
     let config_dir = state.config_dir.lock().unwrap().clone();
-    let image_path = format!("{}/images/{}", config_dir, filename);
+    let tasks_root = paths::resolve(Path::new(&tasks_dir), "")?;
+    let config_root = paths::resolve(Path::new(&config_dir), "")?;
 
-    fs::read(&image_path).map_err(|e| e.to_string())
+    let changed = journal.redo(&journal::Context { tasks_root: &tasks_root, config_root: &config_root, store: &store })?;
+    if changed {
+        history.record("Redo last change".to_string());
+    }
+    Ok(changed)
 }
 
+// Commits that changed a card, newest first. Requires `GIT_HISTORY`.
 #[command]
-async fn start_file_watcher(app_handle: AppHandle, state: State<'_, AppState>, watch_state: State<'_, WatchState>) -> Result<(), String> {
+async fn get_card_history(path: String, state: State<'_, AppState>, history: State<'_, History>) -> Result<Vec<history::CardVersion>, CommandError> {
     let tasks_dir = state.tasks_dir.lock().unwrap().clone();
-    *watch_state.watching.lock().unwrap() = true;
-
-    // Simple file watcher using polling (you might want to use notify crate for better performance)
-    tauri::async_runtime::spawn(async move {
-        let mut last_modified = std::collections::HashMap::new();
-
-        loop {
-            if let Ok(entries) = fs::read_dir(&tasks_dir) {
-                for entry in entries.flatten() {
-                    if let Ok(metadata) = entry.metadata() {
-                        if let Ok(modified) = metadata.modified() {
-                            let path = entry.path();
-                            let path_str = path.to_string_lossy().to_string();
-
-                            if let Some(&last_mod) = last_modified.get(&path_str) {
-                                if modified != last_mod {
-                                    let _ = app_handle.emit("files-changed", ());
-                                }
-                            }
-
-                            last_modified.insert(path_str, modified);
-                        }
-                    }
-                }
-            }
+    paths::check_visible(&path)?;
+    let tasks_root = paths::resolve(Path::new(&tasks_dir), "")?;
+    let full_path = paths::resolve_entry(&tasks_root, &path)?;
+    ensure_history(&history)?;
 
-            tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
-        }
-    });
+    Ok(history::card_history(&tasks_root, &paths::to_relative(&tasks_root, &full_path))?)
+}
+
+// Unified diff of a card between two commits, or between `from` and the
+// file on disk when `to` is omitted
+#[command]
+async fn diff_card_versions(path: String, from: String, to: Option<String>, state: State<'_, AppState>, history: State<'_, History>) -> Result<String, CommandError> {
+    let tasks_dir = state.tasks_dir.lock().unwrap().clone();
+    paths::check_visible(&path)?;
+    let tasks_root = paths::resolve(Path::new(&tasks_dir), "")?;
+    let full_path = paths::resolve_entry(&tasks_root, &path)?;
+    ensure_history(&history)?;
+
+    history::card_diff(&tasks_root, &paths::to_relative(&tasks_root, &full_path), &from, to.as_deref())
+        .map_err(|e| CommandError::from(e).at(&path))
+}
+
+// Overwrites a card with its content from `commit`, recreating it if it was
+// deleted since
+#[command]
+async fn restore_card_version(path: String, commit: String, state: State<'_, AppState>, journal: State<'_, Journal>, history: State<'_, History>, watch_state: State<'_, WatchState>) -> Result<Card, CommandError> {
+    let tasks_dir = state.tasks_dir.lock().unwrap().clone();
+    paths::check_visible(&path)?;
+    let tasks_root = paths::resolve(Path::new(&tasks_dir), "")?;
+    let full_path = paths::resolve_entry(&tasks_root, &path)?;
+    let relative_path = paths::to_relative(&tasks_root, &full_path);
+    ensure_history(&history)?;
+
+    let content = history::card_at(&tasks_root, &relative_path, &commit).map_err(|e| CommandError::from(e).at(&path))?;
+    let previous = fs::read_to_string(&full_path).ok();
+
+    watch_state.own_writes.record(&full_path);
+    if let Some(parent) = full_path.parent() {
+        fs::create_dir_all(parent).map_err(|e| CommandError::io(e, &path))?;
+    }
+    storage::write_atomic(&full_path, &content).map_err(|e| CommandError::io(e, &path))?;
+
+    journal.record(vec![match previous {
+        Some(previous) => Operation::Write { path: relative_path.clone(), content: previous },
+        None => Operation::Trash { path: relative_path.clone() },
+    }]);
+    history.record(history::describe_revert(&relative_path, &commit));
+
+    Card::read(&full_path).map_err(|e| CommandError::io(e, &path))
+}
+
+fn ensure_history(history: &History) -> Result<(), CommandError> {
+    if history.is_enabled() {
+        Ok(())
+    } else {
+        Err(CommandError::new(ErrorCode::Unavailable, "Git history is disabled, set GIT_HISTORY=true to enable it"))
+    }
+}
 
+// Cards of every board, or of `board` and its sub-boards, whose name or
+// content match `query`, best matches first
+#[command]
+async fn search(query: String, board: Option<String>, limit: Option<usize>, state: State<'_, AppState>, watch_state: State<'_, WatchState>, search_index: State<'_, SearchIndex>) -> Result<Vec<search::SearchHit>, CommandError> {
+    let tasks_dir = state.tasks_dir.lock().unwrap().clone();
+    let tasks_root = paths::resolve(Path::new(&tasks_dir), "")?;
+    let watched = watch_state.watchers.lock().unwrap().roots();
+
+    Ok(search_index.search(&tasks_root, &watched, &query, board.as_deref(), limit.unwrap_or(50)))
+}
+
+// Starts watching `board` for the calling window, re-targeting the window's
+// previous watcher when it switches boards
+#[command]
+async fn start_file_watcher(board: Option<String>, app_handle: AppHandle, window: Window, state: State<'_, AppState>, watch_state: State<'_, WatchState>, search_index: State<'_, SearchIndex>) -> Result<(), CommandError> {
+    let tasks_dir = state.tasks_dir.lock().unwrap().clone();
+    let config_dir = state.config_dir.lock().unwrap().clone();
+    let board = board.unwrap_or_default();
+    let tasks_root = paths::resolve(Path::new(&tasks_dir), "")?;
+    let board_root = paths::resolve(Path::new(&tasks_dir), &board)?;
+    let config_root = paths::resolve(Path::new(&config_dir), "")?;
+
+    fs::create_dir_all(&board_root).map_err(|e| CommandError::io(e, &board))?;
+
+    let own_writes = watch_state.own_writes.clone();
+    let search = search_index.marker();
+    watch_state.watchers.lock().unwrap()
+        .subscribe(window.label(), &board, &board_root, |windows| {
+            watcher::watch(app_handle, windows, &tasks_root, &board_root, &config_root, own_writes, search)
+        })
+        .map_err(|e| CommandError::new(ErrorCode::Io, format!("Could not watch the board for changes: {}", e)).at(&board))
+}
+
+#[command]
+async fn stop_file_watcher(window: Window, watch_state: State<'_, WatchState>) -> Result<(), CommandError> {
+    watch_state.watchers.lock().unwrap().unsubscribe(window.label());
     Ok(())
 }
 
+#[command]
+async fn get_file_watcher_status(window: Window, watch_state: State<'_, WatchState>) -> Result<watcher::WatcherStatus, CommandError> {
+    Ok(watch_state.watchers.lock().unwrap().status(window.label()))
+}
+
 fn main() {
+    let tasks_dir = std::env::var("TASKS_DIR").unwrap_or_else(|_| "tasks".to_string());
+    let history = match std::env::var("GIT_HISTORY").as_deref() {
+        Ok("true") | Ok("1") => paths::resolve(Path::new(&tasks_dir), "")
+            .map_err(|e| e.to_string())
+            .and_then(|tasks_root| History::start(&tasks_root).map_err(|e| e.to_string()))
+            .unwrap_or_else(|e| {
+                eprintln!("Failed to enable git history: {}", e);
+                History::default()
+            }),
+        _ => History::default(),
+    };
+
+    let config_dir = std::env::var("CONFIG_DIR").unwrap_or_else(|_| "config".to_string());
+    // Minutes between two clean-ups of unused images, 0 disables them
+    let cleanup_interval: u64 = std::env::var("LOCAL_IMAGES_CLEANUP_INTERVAL").ok().and_then(|minutes| minutes.parse().ok()).unwrap_or(1440);
+    if cleanup_interval > 0 {
+        images::start_cleanup(Path::new(&config_dir).join("images"), Path::new(&tasks_dir).to_path_buf(), Duration::from_secs(cleanup_interval * 60));
+    }
+
+    let mut thumbnails = thumbnails::Config::default();
+    if let Ok(sizes) = std::env::var("LOCAL_IMAGES_THUMBNAIL_SIZES") {
+        thumbnails.sizes = thumbnails::parse_sizes(&sizes);
+    }
+    if std::env::var("LOCAL_IMAGES_THUMBNAIL_FORMAT").is_ok_and(|format| format.eq_ignore_ascii_case("png")) {
+        thumbnails.format = images::ImageFormat::Png;
+    }
+
     tauri::Builder::default()
+        // Background jobs report to stdout and to a file in the app log directory
+        .plugin(tauri_plugin_log::Builder::new().level(log::LevelFilter::Info).build())
         .manage(AppState {
-            config_dir: Mutex::new(std::env::var("CONFIG_DIR").unwrap_or_else(|_| "config".to_string())),
-            tasks_dir: Mutex::new(std::env::var("TASKS_DIR").unwrap_or_else(|_| "tasks".to_string())),
+            config_dir: Mutex::new(config_dir),
+            tasks_dir: Mutex::new(tasks_dir),
             title: Mutex::new(std::env::var("TITLE").unwrap_or_default()),
+            trash_retention_days: std::env::var("TRASH_RETENTION_DAYS").ok().and_then(|days| days.parse().ok()).unwrap_or(30),
+            max_image_size: std::env::var("LOCAL_IMAGES_MAX_SIZE").ok().and_then(|megabytes| megabytes.parse::<u64>().ok()).unwrap_or(10) * 1024 * 1024,
+            thumbnails: thumbnails.clone(),
         })
         .manage(WatchState::default())
+        .manage(ConfigStore::default())
+        .manage(Journal::default())
+        .manage(history)
+        .manage(SearchIndex::default())
+        .manage(thumbnails::Queue::start(thumbnails))
+        .register_asynchronous_uri_scheme_protocol(images::URI_SCHEME, |context, request, responder| {
+            let state = context.app_handle().state::<AppState>();
+            let config_dir = state.config_dir.lock().unwrap().clone();
+            let thumbnails = state.thumbnails.clone();
+            // Files are read, and thumbnails made, off the thread driving the webview
+            tauri::async_runtime::spawn_blocking(move || {
+                responder.respond(images::serve(&Path::new(&config_dir).join("images"), &thumbnails, &request));
+            });
+        })
+        .on_window_event(|window, event| {
+            if let WindowEvent::Destroyed = event {
+                window.state::<WatchState>().watchers.lock().unwrap().unsubscribe(window.label());
+            }
+        })
         .invoke_handler(tauri::generate_handler![
             get_tags,
             update_tag_background_color,
             get_title,
             get_resource,
+            get_boards,
+            get_card,
+            query_cards,
             create_resource,
             update_resource,
+            update_card_field,
             delete_resource,
+            list_trash,
+            restore_trash,
+            purge_trash,
             upload_image,
             update_sort,
             get_sort,
-            get_image,
-            start_file_watcher
+            get_done_lanes,
+            update_done_lanes,
+            get_agenda,
+            list_views,
+            create_view,
+            update_view,
+            delete_view,
+            undo,
+            redo,
+            get_card_history,
+            diff_card_versions,
+            restore_card_version,
+            search,
+            clean_images,
+            migrate_images,
+            start_file_watcher,
+            stop_file_watcher,
+            get_file_watcher_status
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
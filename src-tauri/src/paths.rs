@@ -0,0 +1,287 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+// Errors produced while mapping a frontend path onto the filesystem
+#[derive(Debug)]
+pub enum PathError {
+    OutsideWorkspace(String),
+    WorkspaceRoot(String),
+    // The path and why its last component cannot be used as a name
+    InvalidName(String, &'static str),
+    // The path reaches a hidden entry such as `.trash`
+    Hidden(String),
+    Io(io::Error),
+}
+
+impl fmt::Display for PathError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PathError::OutsideWorkspace(path) => write!(f, "path outside workspace: {}", path),
+            PathError::WorkspaceRoot(path) => write!(f, "refusing to modify workspace root: {}", path),
+            PathError::InvalidName(path, reason) => write!(f, "invalid name {}: {}", path, reason),
+            PathError::Hidden(path) => write!(f, "path reaches a hidden entry: {}", path),
+            PathError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for PathError {}
+
+impl From<io::Error> for PathError {
+    fn from(e: io::Error) -> Self {
+        PathError::Io(e)
+    }
+}
+
+// Resolves a path sent by the frontend (e.g. "/board/lane/card.md") inside `root`.
+//
+// The path is normalized lexically first, so any `..` that would climb above
+// `root` is rejected before touching the disk. Symlinks are allowed, but only
+// when everything they point to stays inside the canonical `root`; dangling
+// links are rejected because their target cannot be verified. The returned
+// path keeps the final component as given, so renaming or deleting a symlink
+// acts on the link itself.
+pub fn resolve(root: &Path, path: &str) -> Result<PathBuf, PathError> {
+    let root = canonical_root(root)?;
+    let relative = normalize(path).ok_or_else(|| PathError::OutsideWorkspace(path.to_string()))?;
+    let resolved = root.join(relative);
+
+    let existing = canonicalize_existing(&resolved, path)?;
+    if !existing.starts_with(&root) {
+        return Err(PathError::OutsideWorkspace(path.to_string()));
+    }
+
+    Ok(resolved)
+}
+
+// Same as `resolve`, but refuses paths pointing at `root` itself. Used by
+// commands that rename or delete, where an empty path must never be allowed
+// to act on the whole workspace.
+pub fn resolve_entry(root: &Path, path: &str) -> Result<PathBuf, PathError> {
+    let resolved = resolve(root, path)?;
+    if resolved == canonical_root(root)? {
+        return Err(PathError::WorkspaceRoot(path.to_string()));
+    }
+    Ok(resolved)
+}
+
+fn canonical_root(root: &Path) -> Result<PathBuf, PathError> {
+    fs::create_dir_all(root)?;
+    Ok(root.canonicalize()?)
+}
+
+// Drops root and `.` components and folds `..` into its parent. Returns `None`
+// when the path climbs out of the workspace or carries a drive prefix.
+fn normalize(path: &str) -> Option<PathBuf> {
+    let mut normalized = PathBuf::new();
+
+    for component in Path::new(path).components() {
+        match component {
+            Component::RootDir | Component::CurDir => {}
+            Component::Normal(part) => normalized.push(part),
+            Component::ParentDir => {
+                if !normalized.pop() {
+                    return None;
+                }
+            }
+            Component::Prefix(_) => return None,
+        }
+    }
+
+    Some(normalized)
+}
+
+// Canonicalizes the deepest ancestor of `path` that exists on disk
+fn canonicalize_existing(path: &Path, original: &str) -> Result<PathBuf, PathError> {
+    let mut current = path;
+
+    loop {
+        match current.canonicalize() {
+            Ok(canonical) => return Ok(canonical),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                if fs::symlink_metadata(current).is_ok() {
+                    return Err(PathError::OutsideWorkspace(original.to_string()));
+                }
+                current = match current.parent() {
+                    Some(parent) => parent,
+                    None => return Err(e.into()),
+                };
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+// Replaces characters that are not allowed in card and lane names, keeping
+// `/` as the separator between board, lane and card.
+pub fn sanitize(path: &str) -> String {
+    path.split('/')
+        .map(|part| {
+            part.chars()
+                .map(|c| if "<>:\"\\|?*".contains(c) { ' ' } else { c })
+                .collect::<String>()
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+// Checks the name a card or lane is created or renamed with. Hidden names
+// would vanish from the board, as `get_resource` skips them.
+pub fn validate_name(path: &str) -> Result<(), PathError> {
+    let name = path.trim_end_matches('/').rsplit('/').next().unwrap_or_default();
+    let stem = name.strip_suffix(".md").unwrap_or(name);
+
+    let reason = if stem.trim().is_empty() {
+        "it is empty"
+    } else if name.starts_with('.') {
+        "it cannot start with a dot"
+    } else if name.chars().any(char::is_control) {
+        "it cannot contain control characters"
+    } else {
+        return Ok(());
+    };
+
+    Err(PathError::InvalidName(path.to_string(), reason))
+}
+
+// Refuses paths reaching hidden entries, such as `.trash`, `.git` or `.images`,
+// which hold app data rather than boards, lanes and cards. Checked once `..`
+// is folded, so "/lane/../.trash/card.md" is refused too.
+pub fn check_visible(path: &str) -> Result<(), PathError> {
+    let normalized = normalize(path).ok_or_else(|| PathError::OutsideWorkspace(path.to_string()))?;
+    if normalized.components().any(|component| component.as_os_str().to_string_lossy().starts_with('.')) {
+        return Err(PathError::Hidden(path.to_string()));
+    }
+    Ok(())
+}
+
+// Inverse of `resolve`: turns a path inside `root` back into the
+// "/board/lane/card.md" form used by the frontend
+pub fn to_relative(root: &Path, path: &Path) -> String {
+    path.strip_prefix(root)
+        .unwrap_or(path)
+        .components()
+        .filter_map(|component| match component {
+            Component::Normal(part) => Some(format!("/{}", part.to_string_lossy())),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    #[test]
+    fn resolves_paths_inside_the_root() {
+        let root = TempDir::new("paths-inside");
+        fs::create_dir_all(root.path().join("board/lane")).unwrap();
+
+        assert_eq!(resolve(root.path(), "/board/lane").unwrap(), root.path().join("board/lane"));
+        assert_eq!(resolve(root.path(), "board/./lane/").unwrap(), root.path().join("board/lane"));
+        assert_eq!(resolve(root.path(), "/board/other/../lane").unwrap(), root.path().join("board/lane"));
+    }
+
+    #[test]
+    fn rejects_parent_traversal() {
+        let root = TempDir::new("paths-traversal");
+
+        for path in ["..", "../outside", "/board/../../outside", "board/../../../etc/passwd"] {
+            assert!(matches!(resolve(root.path(), path), Err(PathError::OutsideWorkspace(_))), "{}", path);
+        }
+    }
+
+    #[test]
+    fn keeps_absolute_paths_inside_the_root() {
+        let root = TempDir::new("paths-absolute");
+
+        assert_eq!(resolve(root.path(), "/etc/passwd").unwrap(), root.path().join("etc/passwd"));
+        assert_eq!(resolve(root.path(), "//board").unwrap(), root.path().join("board"));
+    }
+
+    #[test]
+    fn resolves_leaves_that_do_not_exist_yet() {
+        let root = TempDir::new("paths-missing");
+        fs::create_dir_all(root.path().join("board")).unwrap();
+
+        assert_eq!(resolve(root.path(), "/board/new.md").unwrap(), root.path().join("board/new.md"));
+        assert_eq!(resolve(root.path(), "/new board/new lane/new.md").unwrap(), root.path().join("new board/new lane/new.md"));
+    }
+
+    #[test]
+    fn refuses_the_root_itself_as_an_entry() {
+        let root = TempDir::new("paths-root");
+
+        assert_eq!(resolve(root.path(), "").unwrap(), root.path());
+        for path in ["", "/", ".", "/board/..", "//"] {
+            assert!(matches!(resolve_entry(root.path(), path), Err(PathError::WorkspaceRoot(_))), "{}", path);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn rejects_symlinks_escaping_the_root() {
+        let root = TempDir::new("paths-symlink");
+        let outside = TempDir::new("paths-symlink-outside");
+        fs::write(outside.path().join("secret.md"), "secret").unwrap();
+        std::os::unix::fs::symlink(outside.path(), root.path().join("escape")).unwrap();
+        std::os::unix::fs::symlink(outside.path().join("secret.md"), root.path().join("secret.md")).unwrap();
+        std::os::unix::fs::symlink(root.path().join("gone"), root.path().join("dangling")).unwrap();
+
+        for path in ["/escape", "/escape/secret.md", "/escape/new.md", "/secret.md", "/dangling", "/dangling/new.md"] {
+            assert!(matches!(resolve(root.path(), path), Err(PathError::OutsideWorkspace(_))), "{}", path);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn allows_symlinks_staying_inside_the_root() {
+        let root = TempDir::new("paths-symlink-inside");
+        fs::create_dir_all(root.path().join("board/lane")).unwrap();
+        std::os::unix::fs::symlink(root.path().join("board/lane"), root.path().join("board/alias")).unwrap();
+
+        // The link itself is returned, so renaming or deleting acts on it
+        assert_eq!(resolve(root.path(), "/board/alias").unwrap(), root.path().join("board/alias"));
+        assert_eq!(resolve(root.path(), "/board/alias/card.md").unwrap(), root.path().join("board/alias/card.md"));
+    }
+
+    #[test]
+    fn rejects_hidden_entries_anywhere() {
+        for path in ["/.trash", "/.trash/lane/card.md", "/board/.git/config", "board/lane/../.images/a.png", "/board/lane/.card.md.tmp"] {
+            assert!(matches!(check_visible(path), Err(PathError::Hidden(_))), "{}", path);
+        }
+        assert!(matches!(check_visible("/../.trash"), Err(PathError::OutsideWorkspace(_))));
+
+        for path in ["", "/", "/board/lane/card.md", "/board/lane/v1.2.md", "/board/./lane", "/board/.hidden/../lane"] {
+            assert!(check_visible(path).is_ok(), "{}", path);
+        }
+    }
+
+    #[test]
+    fn sanitizes_each_component() {
+        assert_eq!(sanitize("/board/a<b>:c/\"d\"|e?*.md"), "/board/a b  c/ d  e  .md");
+        assert_eq!(sanitize("/board\\lane"), "/board lane");
+    }
+
+    #[test]
+    fn validates_the_last_component() {
+        assert!(validate_name("/board/lane/card.md").is_ok());
+        assert!(validate_name("/board/lane/").is_ok());
+
+        for (path, reason) in [
+            ("/board/lane/.md", "it is empty"),
+            ("/board/lane/  .md", "it is empty"),
+            ("/", "it is empty"),
+            ("/board/.hidden", "it cannot start with a dot"),
+            ("/board/lane/a\u{7}b.md", "it cannot contain control characters"),
+        ] {
+            match validate_name(path) {
+                Err(PathError::InvalidName(_, found)) => assert_eq!(found, reason, "{}", path),
+                other => panic!("{} gave {:?}", path, other),
+            }
+        }
+    }
+}
@@ -0,0 +1,602 @@
+use crate::card::Card;
+use crate::due;
+use crate::frontmatter;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::fmt;
+
+// Filter expression over cards, e.g.
+// `tag:bug AND due<2026-11-01 AND lane:"In Progress" AND text:"login"`.
+//
+// Terms are `field` + operator + value, or a bare value matched against the
+// name and content. They combine with AND (also implied between terms), OR,
+// NOT or a leading `-`, and parentheses. Keywords are uppercase so lowercase
+// "and" or "or" can still be searched for.
+#[derive(Debug)]
+pub enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Term(Term),
+}
+
+#[derive(Debug)]
+pub struct Term {
+    // `None` for bare values
+    field: Option<String>,
+    op: Op,
+    value: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Op {
+    // `:` is equality for most fields, and "contains" for `name` and `text`
+    Match,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug)]
+pub struct QueryError {
+    pub message: String,
+    // Character offset in the query
+    pub position: usize,
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at character {}", self.message, self.position + 1)
+    }
+}
+
+// Parses `query`, an empty query gives `None` and matches every card
+pub fn parse(query: &str) -> Result<Option<Expr>, QueryError> {
+    let tokens = lex(query)?;
+    if tokens.is_empty() {
+        return Ok(None);
+    }
+
+    let mut parser = Parser { tokens, position: 0, end: query.chars().count() };
+    let expr = parser.or()?;
+    match parser.tokens.get(parser.position) {
+        Some((position, _)) => Err(QueryError { message: "Unexpected \")\"".to_string(), position: *position }),
+        None => Ok(Some(expr)),
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Word(String),
+    Quoted(String),
+    Op(Op),
+    Open,
+    Close,
+    And,
+    Or,
+    Not,
+}
+
+fn lex(query: &str) -> Result<Vec<(usize, Token)>, QueryError> {
+    let chars: Vec<char> = query.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let start = i;
+        let after_op = matches!(tokens.last(), Some((_, Token::Op(_))));
+        match chars[i] {
+            c if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            '"' => {
+                let mut value = String::new();
+                i += 1;
+                loop {
+                    match chars.get(i) {
+                        Some('"') => break,
+                        Some('\\') if i + 1 < chars.len() => {
+                            value.push(chars[i + 1]);
+                            i += 2;
+                        }
+                        Some(c) => {
+                            value.push(*c);
+                            i += 1;
+                        }
+                        None => return Err(QueryError { message: "Unclosed quote".to_string(), position: start }),
+                    }
+                }
+                i += 1;
+                tokens.push((start, Token::Quoted(value)));
+            }
+            '(' if !after_op => {
+                i += 1;
+                tokens.push((start, Token::Open));
+            }
+            ')' if !after_op => {
+                i += 1;
+                tokens.push((start, Token::Close));
+            }
+            '-' if !after_op && chars.get(i + 1).is_some_and(|c| !c.is_whitespace()) => {
+                i += 1;
+                tokens.push((start, Token::Not));
+            }
+            ':' | '=' | '<' | '>' | '!' if !after_op => {
+                let next = chars.get(i + 1).copied();
+                let (op, len) = match (chars[i], next) {
+                    (':', _) => (Op::Match, 1),
+                    ('=', _) => (Op::Eq, 1),
+                    ('!', Some('=')) => (Op::Ne, 2),
+                    ('<', Some('=')) => (Op::Le, 2),
+                    ('<', _) => (Op::Lt, 1),
+                    ('>', Some('=')) => (Op::Ge, 2),
+                    ('>', _) => (Op::Gt, 1),
+                    _ => return Err(QueryError { message: "Expected \"!=\"".to_string(), position: start }),
+                };
+                i += len;
+                tokens.push((start, Token::Op(op)));
+            }
+            _ => {
+                // Values after an operator run until whitespace so they may
+                // hold operator characters, e.g. `due<2026-11-01T10:00`
+                let is_end = |c: char| {
+                    c.is_whitespace() || c == '"' || c == ')' || (!after_op && (c == '(' || ":=<>!".contains(c)))
+                };
+                while i < chars.len() && !is_end(chars[i]) {
+                    i += 1;
+                }
+                let word: String = chars[start..i].iter().collect();
+                tokens.push((start, match word.as_str() {
+                    "AND" if !after_op => Token::And,
+                    "OR" if !after_op => Token::Or,
+                    "NOT" if !after_op => Token::Not,
+                    _ => Token::Word(word),
+                }));
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    position: usize,
+    // Length of the query, reported when it ends too early
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position).map(|(_, token)| token)
+    }
+
+    fn next(&mut self) -> Option<(usize, Token)> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn error(&self, message: &str) -> QueryError {
+        let position = self.tokens.get(self.position).map_or(self.end, |(position, _)| *position);
+        QueryError { message: message.to_string(), position }
+    }
+
+    fn or(&mut self) -> Result<Expr, QueryError> {
+        let mut expr = self.and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.position += 1;
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, QueryError> {
+        let mut expr = self.unary()?;
+        loop {
+            match self.peek() {
+                Some(Token::And) => self.position += 1,
+                // Terms next to each other must all match
+                Some(Token::Word(_)) | Some(Token::Quoted(_)) | Some(Token::Open) | Some(Token::Not) => {}
+                _ => return Ok(expr),
+            }
+            expr = Expr::And(Box::new(expr), Box::new(self.unary()?));
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, QueryError> {
+        match self.peek() {
+            Some(Token::Not) => {
+                self.position += 1;
+                Ok(Expr::Not(Box::new(self.unary()?)))
+            }
+            Some(Token::Open) => {
+                self.position += 1;
+                let expr = self.or()?;
+                match self.next() {
+                    Some((_, Token::Close)) => Ok(expr),
+                    _ => {
+                        self.position -= 1;
+                        Err(self.error("Expected \")\""))
+                    }
+                }
+            }
+            _ => self.term(),
+        }
+    }
+
+    fn term(&mut self) -> Result<Expr, QueryError> {
+        let value = match self.next() {
+            Some((_, Token::Word(value))) | Some((_, Token::Quoted(value))) => value,
+            _ => {
+                self.position -= 1;
+                return Err(self.error("Expected a search term"));
+            }
+        };
+
+        let op = match self.peek() {
+            Some(Token::Op(op)) => *op,
+            _ => return Ok(Expr::Term(Term { field: None, op: Op::Match, value })),
+        };
+        self.position += 1;
+
+        match self.next() {
+            Some((_, Token::Word(operand))) | Some((_, Token::Quoted(operand))) => {
+                Ok(Expr::Term(Term { field: Some(value.to_lowercase()), op, value: operand }))
+            }
+            _ => {
+                self.position -= 1;
+                Err(self.error(&format!("Expected a value for \"{}\"", value)))
+            }
+        }
+    }
+}
+
+impl Expr {
+    pub fn matches(&self, card: &Card, lane: &str) -> bool {
+        match self {
+            Expr::And(left, right) => left.matches(card, lane) && right.matches(card, lane),
+            Expr::Or(left, right) => left.matches(card, lane) || right.matches(card, lane),
+            Expr::Not(expr) => !expr.matches(card, lane),
+            Expr::Term(term) => term.matches(card, lane),
+        }
+    }
+}
+
+impl Term {
+    fn matches(&self, card: &Card, lane: &str) -> bool {
+        let field = match &self.field {
+            Some(field) => field.as_str(),
+            None => return contains(&card.name, &self.value) || contains(&card.content, &self.value),
+        };
+
+        // `has:due` matches cards with a due date, `has:tag` cards with tags
+        if field == "has" {
+            let present = !field_values(card, lane, &self.value.to_lowercase()).is_empty();
+            return match self.op {
+                Op::Ne => !present,
+                _ => present,
+            };
+        }
+
+        let values = field_values(card, lane, field);
+        match self.op {
+            Op::Match if field == "name" || field == "text" => values.iter().any(|value| contains(value, &self.value)),
+            Op::Ne => !values.iter().any(|value| compare(field, value, &self.value) == Ordering::Equal),
+            op => values.iter().any(|value| {
+                let ordering = compare(field, value, &self.value);
+                match op {
+                    Op::Match | Op::Eq => ordering == Ordering::Equal,
+                    Op::Lt => ordering == Ordering::Less,
+                    Op::Le => ordering != Ordering::Greater,
+                    Op::Gt => ordering == Ordering::Greater,
+                    Op::Ge => ordering != Ordering::Less,
+                    Op::Ne => unreachable!(),
+                }
+            }),
+        }
+    }
+}
+
+// Values of `field` for a card, empty when it has none. Fields other than
+// the built-in ones are read from the frontmatter, then from `[key:value]`
+// tokens.
+fn field_values(card: &Card, lane: &str, field: &str) -> Vec<String> {
+    match field {
+        "tag" | "tags" => card.tags.clone(),
+        "lane" => vec![lane.to_string()],
+        "name" => vec![card.name.clone()],
+        "text" => vec![card.content.clone()],
+        "due" => card.due.as_ref().or(card.due_date.as_ref()).cloned().into_iter().collect(),
+        "priority" => card.priority.iter().cloned().collect(),
+        "assignee" => card.assignee.iter().cloned().collect(),
+        "status" => card.status.iter().cloned().collect(),
+        _ => {
+            let key = card.frontmatter.keys().find(|key| key.to_lowercase() == field);
+            match key {
+                Some(key) => match frontmatter::field_string(&card.frontmatter, key) {
+                    Some(value) => vec![value],
+                    None => frontmatter::field_list(&card.frontmatter, key),
+                },
+                None => card.tokens.iter()
+                    .filter(|token| token.key.to_lowercase() == field)
+                    .map(|token| token.value.clone())
+                    .collect(),
+            }
+        }
+    }
+}
+
+fn contains(text: &str, value: &str) -> bool {
+    text.to_lowercase().contains(&value.to_lowercase())
+}
+
+// Due dates compare as dates, so `due<tomorrow` or `due:"jan 5"` work and the
+// time only matters when both sides have one. Numbers compare as numbers,
+// anything else case-insensitively as text.
+fn compare(field: &str, value: &str, operand: &str) -> Ordering {
+    if field == "due" {
+        let today = due::today();
+        if let (Some(value), Some(operand)) = (due::parse(value, today), due::parse(operand, today)) {
+            return value.date.cmp(&operand.date).then_with(|| match (value.time, operand.time) {
+                (Some(value), Some(operand)) => value.cmp(&operand),
+                _ => Ordering::Equal,
+            });
+        }
+    }
+
+    match (value.trim().parse::<f64>(), operand.trim().parse::<f64>()) {
+        (Ok(value), Ok(operand)) => value.partial_cmp(&operand).unwrap_or(Ordering::Equal),
+        _ => value.trim().to_lowercase().cmp(&operand.trim().to_lowercase()),
+    }
+}
+
+// Sort modes of the board, with the same meaning as in the frontend
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum SortBy {
+    Name,
+    Tags,
+    Due,
+    // Most recently updated first
+    LastUpdated,
+    // Oldest first
+    CreatedFirst,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum SortDirection {
+    Asc,
+    Desc,
+}
+
+// A card matching a query, along with where it lives
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueryResult {
+    pub lane: String,
+    pub path: String,
+    #[serde(flatten)]
+    pub card: Card,
+}
+
+// Cards without tags or due date stay last whatever the direction
+pub fn sort(results: &mut [QueryResult], by: SortBy, direction: SortDirection) {
+    let directed = |ordering: Ordering| match direction {
+        SortDirection::Asc => ordering,
+        SortDirection::Desc => ordering.reverse(),
+    };
+    let missing_last = |a: Option<String>, b: Option<String>| match (a, b) {
+        (Some(a), Some(b)) => directed(a.cmp(&b)),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    };
+
+    results.sort_by(|a, b| {
+        let (a, b) = (&a.card, &b.card);
+        let ordering = match by {
+            SortBy::Name => directed(a.name.to_lowercase().cmp(&b.name.to_lowercase())),
+            SortBy::Tags => missing_last(a.tags.first().map(|tag| tag.to_lowercase()), b.tags.first().map(|tag| tag.to_lowercase())),
+            SortBy::Due => missing_last(a.due.clone(), b.due.clone()),
+            SortBy::LastUpdated => directed(b.last_updated.cmp(&a.last_updated)),
+            SortBy::CreatedFirst => directed(a.created_at.cmp(&b.created_at)),
+        };
+        ordering.then_with(|| a.name.cmp(&b.name))
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    // Writes the parsed expression back with explicit grouping
+    fn show(expr: &Expr) -> String {
+        match expr {
+            Expr::And(left, right) => format!("({} AND {})", show(left), show(right)),
+            Expr::Or(left, right) => format!("({} OR {})", show(left), show(right)),
+            Expr::Not(expr) => format!("-{}", show(expr)),
+            Expr::Term(term) => {
+                let op = match term.op {
+                    Op::Match => ":",
+                    Op::Eq => "=",
+                    Op::Ne => "!=",
+                    Op::Lt => "<",
+                    Op::Le => "<=",
+                    Op::Gt => ">",
+                    Op::Ge => ">=",
+                };
+                match &term.field {
+                    Some(field) => format!("{}{}[{}]", field, op, term.value),
+                    None => format!("[{}]", term.value),
+                }
+            }
+        }
+    }
+
+    fn parsed(query: &str) -> String {
+        match parse(query) {
+            Ok(Some(expr)) => show(&expr),
+            Ok(None) => String::new(),
+            Err(e) => panic!("{} gave {}", query, e),
+        }
+    }
+
+    fn error(query: &str) -> (String, usize) {
+        match parse(query) {
+            Err(e) => (e.message, e.position),
+            Ok(expr) => panic!("{} gave {:?}", query, expr),
+        }
+    }
+
+    fn card(name: &str, content: &str) -> Card {
+        let metadata = fs::metadata(std::env::temp_dir()).unwrap();
+        Card::parse(name.to_string(), content.to_string(), &metadata)
+    }
+
+    #[test]
+    fn parses_empty_queries() {
+        assert_eq!(parsed(""), "");
+        assert_eq!(parsed("  \t "), "");
+    }
+
+    #[test]
+    fn parses_terms_and_operators() {
+        assert_eq!(parsed("login"), "[login]");
+        assert_eq!(parsed("TAG:Bug"), "tag:[Bug]");
+        assert_eq!(parsed("priority=2"), "priority=[2]");
+        assert_eq!(parsed("status!=done"), "status!=[done]");
+        assert_eq!(parsed("due<tomorrow"), "due<[tomorrow]");
+        assert_eq!(parsed("due<=2026-11-01"), "due<=[2026-11-01]");
+        assert_eq!(parsed("priority>1"), "priority>[1]");
+        assert_eq!(parsed("priority >= 1"), "priority>=[1]");
+    }
+
+    #[test]
+    fn parses_boolean_operators() {
+        assert_eq!(parsed("tag:bug due<2026-11-01"), "(tag:[bug] AND due<[2026-11-01])");
+        assert_eq!(parsed("a AND b OR c"), "(([a] AND [b]) OR [c])");
+        assert_eq!(parsed("a OR b c"), "([a] OR ([b] AND [c]))");
+        assert_eq!(parsed("(a OR b) c"), "(([a] OR [b]) AND [c])");
+        assert_eq!(parsed("a or b"), "(([a] AND [or]) AND [b])");
+        assert_eq!(parsed("NOT (a OR b)"), "-([a] OR [b])");
+        assert_eq!(parsed("NOT NOT a"), "--[a]");
+    }
+
+    #[test]
+    fn parses_negation_with_a_dash() {
+        assert_eq!(parsed("-tag:bug"), "-tag:[bug]");
+        assert_eq!(parsed("a -b"), "([a] AND -[b])");
+        assert_eq!(parsed("-(a b)"), "-([a] AND [b])");
+        assert_eq!(parsed("pre-release"), "[pre-release]");
+        assert_eq!(parsed("a - b"), "(([a] AND [-]) AND [b])");
+    }
+
+    #[test]
+    fn parses_quoted_phrases() {
+        assert_eq!(parsed("lane:\"In Progress\""), "lane:[In Progress]");
+        assert_eq!(parsed("\"fix the login\""), "[fix the login]");
+        assert_eq!(parsed("\"say \\\"hi\\\"\""), "[say \"hi\"]");
+        assert_eq!(parsed("\"AND\" \"(\""), "([AND] AND [(])");
+        assert_eq!(parsed("\"\""), "[]");
+    }
+
+    #[test]
+    fn keeps_operator_characters_in_values() {
+        assert_eq!(parsed("due<2026-11-01T10:00"), "due<[2026-11-01T10:00]");
+        assert_eq!(parsed("text:a:b"), "text:[a:b]");
+        assert_eq!(parsed("name:a(b"), "name:[a(b]");
+        assert_eq!(parsed("tag:-x"), "tag:[-x]");
+        assert_eq!(parsed("tag:AND tag:OR"), "(tag:[AND] AND tag:[OR])");
+        assert_eq!(parsed("(tag:bug)"), "tag:[bug]");
+    }
+
+    #[test]
+    fn reports_errors_with_their_position() {
+        let expected = [
+            ("\"abc", "Unclosed quote", 0),
+            ("tag:\"x", "Unclosed quote", 4),
+            ("a !b", "Expected \"!=\"", 2),
+            ("(a", "Expected \")\"", 2),
+            ("(a b c", "Expected \")\"", 6),
+            ("a)", "Unexpected \")\"", 1),
+            ("tag:", "Expected a value for \"tag\"", 4),
+            ("a AND", "Expected a search term", 5),
+            ("OR a", "Expected a search term", 0),
+            ("()", "Expected a search term", 1),
+            ("a NOT", "Expected a search term", 5),
+            // Positions count characters, not bytes
+            ("é (", "Expected a search term", 3),
+        ];
+        for (query, message, position) in expected {
+            assert_eq!(error(query), (message.to_string(), position), "{}", query);
+        }
+    }
+
+    #[test]
+    fn displays_errors_from_one() {
+        let e = parse("a)").unwrap_err();
+        assert_eq!(e.to_string(), "Unexpected \")\" at character 2");
+    }
+
+    #[test]
+    fn matches_cards() {
+        let card = card("Login page", "---\ntags: [bug]\npriority: 2\ndue: 2026-11-01\n---\nFix the redirect [owner:sam] [tag:ui]\n");
+        let matches = |query: &str| parse(query).unwrap().unwrap().matches(&card, "In Progress");
+
+        assert!(matches("login"));
+        assert!(matches("redirect"));
+        assert!(matches("tag:BUG tag:ui"));
+        assert!(matches("lane:\"in progress\""));
+        assert!(matches("name:page"));
+        assert!(matches("priority>=2 priority<10"));
+        assert!(matches("due<2026-11-02 due>=2026-11-01"));
+        assert!(matches("owner:sam has:due has:tag"));
+        assert!(matches("-tag:feature"));
+        assert!(matches("tag:feature OR text:fix"));
+        assert!(!matches("NOT login"));
+        assert!(!matches("status:done"));
+        assert!(!matches("has!=due"));
+        assert!(!matches("tag!=bug"));
+        assert!(!matches("due>2026-11-01"));
+    }
+
+    fn result(name: &str, content: &str) -> QueryResult {
+        QueryResult { lane: "Lane".to_string(), path: format!("/board/Lane/{}.md", name), card: card(name, content) }
+    }
+
+    fn sorted(by: SortBy, direction: SortDirection) -> Vec<String> {
+        let mut results = vec![
+            result("b", "[tag:zeta] [due:2026-12-01]"),
+            result("C", ""),
+            result("a", "[tag:Alpha] [due:2026-11-01]"),
+        ];
+        sort(&mut results, by, direction);
+        results.into_iter().map(|result| result.card.name).collect()
+    }
+
+    #[test]
+    fn sorts_results() {
+        assert_eq!(sorted(SortBy::Name, SortDirection::Asc), ["a", "b", "C"]);
+        assert_eq!(sorted(SortBy::Name, SortDirection::Desc), ["C", "b", "a"]);
+        assert_eq!(sorted(SortBy::Tags, SortDirection::Asc), ["a", "b", "C"]);
+        assert_eq!(sorted(SortBy::Due, SortDirection::Asc), ["a", "b", "C"]);
+        // Cards without a due date stay last
+        assert_eq!(sorted(SortBy::Due, SortDirection::Desc), ["b", "a", "C"]);
+        assert_eq!(sorted(SortBy::Tags, SortDirection::Desc), ["b", "a", "C"]);
+    }
+
+    #[test]
+    fn reads_sort_modes() {
+        assert_eq!(serde_json::from_str::<SortBy>("\"lastUpdated\"").unwrap(), SortBy::LastUpdated);
+        assert_eq!(serde_json::from_str::<SortBy>("\"createdFirst\"").unwrap(), SortBy::CreatedFirst);
+        assert_eq!(serde_json::from_str::<SortDirection>("\"desc\"").unwrap(), SortDirection::Desc);
+        assert!(serde_json::from_str::<SortBy>("\"size\"").is_err());
+    }
+}
@@ -0,0 +1,553 @@
+use notify::{Event, EventKind};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+// BM25 parameters
+const K1: f64 = 1.2;
+const B: f64 = 0.75;
+// A query term matching the start of a longer word counts for less than an exact match
+const PREFIX_WEIGHT: f64 = 0.5;
+// Query terms found in the card name weigh as much as this many occurrences in its content
+const NAME_BOOST: f64 = 3.0;
+// Characters of context kept around the first match in a snippet
+const SNIPPET_CONTEXT: usize = 60;
+// Stale paths kept between two searches, past which the whole tree is checked instead
+const MAX_STALE: usize = 10_000;
+
+// Inverted index of the names and contents of every card under the tasks
+// directory, across all boards. It is built on the first search and kept
+// current by the board watchers of the open windows, whose events only mark
+// paths as stale; they are re-read right before the next search. Boards no
+// watcher covered since the previous search are compared with the disk.
+#[derive(Default)]
+pub struct SearchIndex {
+    index: Arc<Mutex<Index>>,
+}
+
+#[derive(Default)]
+struct Index {
+    root: Option<PathBuf>,
+    // Keyed by frontend path, e.g. "/board/lane/card.md"
+    docs: HashMap<String, Doc>,
+    // Term to the documents containing it and how many times
+    postings: BTreeMap<String, HashMap<String, u32>>,
+    total_length: usize,
+    // Paths changed since the last search
+    stale: HashSet<PathBuf>,
+    // Set when events were lost, so no watched board is taken as up to date
+    untrusted: bool,
+    // Board watchers running at the last search, by root and id
+    watched: Vec<(PathBuf, u64)>,
+}
+
+struct Doc {
+    board: String,
+    lane: String,
+    name: String,
+    content: String,
+    name_terms: HashSet<String>,
+    length: usize,
+    // Compared with the file to tell whether it changed when no watcher did
+    modified: Option<SystemTime>,
+    size: u64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchHit {
+    pub path: String,
+    pub board: String,
+    pub lane: String,
+    pub card: String,
+    pub score: f64,
+    // Excerpt of the content around the first match, split so matched words
+    // can be highlighted without the frontend rendering card content as HTML
+    pub snippet: Vec<SnippetPart>,
+}
+
+#[derive(Serialize)]
+pub struct SnippetPart {
+    pub text: String,
+    pub highlight: bool,
+}
+
+impl SearchIndex {
+    // Ranked hits for `query`, optionally restricted to `board` and its
+    // sub-boards. Every query term has to match, either a whole word or the
+    // start of one. `watched` lists the running board watchers, see
+    // `Watchers::roots`.
+    pub fn search(&self, tasks_root: &Path, watched: &[(PathBuf, u64)], query: &str, board: Option<&str>, limit: usize) -> Vec<SearchHit> {
+        let mut index = self.index.lock().unwrap();
+        if index.root.as_deref() != Some(tasks_root) {
+            *index = Index { root: Some(tasks_root.to_path_buf()), ..Index::default() };
+        }
+        index.refresh(watched);
+
+        let terms = tokenize(query);
+        if terms.is_empty() {
+            return Vec::new();
+        }
+        let board = board.map(|board| board.trim_end_matches('/')).filter(|board| !board.is_empty());
+
+        let mut hits: Vec<SearchHit> = index.score(&terms)
+            .into_iter()
+            .filter_map(|(path, score)| {
+                let doc = &index.docs[&path];
+                if let Some(board) = board {
+                    if doc.board != board && !doc.board.starts_with(&format!("{}/", board)) {
+                        return None;
+                    }
+                }
+                Some(SearchHit {
+                    board: doc.board.clone(),
+                    lane: doc.lane.clone(),
+                    card: doc.name.clone(),
+                    score,
+                    snippet: snippet(&doc.content, &terms),
+                    path,
+                })
+            })
+            .collect();
+
+        hits.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(std::cmp::Ordering::Equal).then_with(|| a.path.cmp(&b.path)));
+        hits.truncate(limit);
+        hits
+    }
+
+    // Handle the board watchers report their events to
+    pub fn marker(&self) -> StaleMarker {
+        StaleMarker(Arc::clone(&self.index))
+    }
+}
+
+#[derive(Clone)]
+pub struct StaleMarker(Arc<Mutex<Index>>);
+
+impl StaleMarker {
+    pub fn mark(&self, event: &notify::Result<Event>) {
+        let mut index = self.0.lock().unwrap();
+        if index.root.is_none() || index.untrusted {
+            return;
+        }
+        match event {
+            Ok(event) if event.need_rescan() => index.untrusted = true,
+            Ok(Event { kind: EventKind::Access(_), .. }) => {}
+            Ok(event) => index.stale.extend(event.paths.iter().cloned()),
+            Err(_) => index.untrusted = true,
+        }
+        if index.stale.len() > MAX_STALE {
+            index.stale.clear();
+            index.untrusted = true;
+        }
+    }
+}
+
+impl Index {
+    fn refresh(&mut self, watched: &[(PathBuf, u64)]) {
+        let root = match self.root.clone() {
+            Some(root) => root,
+            None => return,
+        };
+
+        // Only a watcher already running at the previous search has reported
+        // every change below its root since then, ids are never reused
+        let trusted: Vec<PathBuf> = watched.iter()
+            .filter(|watcher| !self.untrusted && self.watched.contains(watcher))
+            .map(|(path, _)| path.clone())
+            .collect();
+        self.watched = watched.to_vec();
+        self.untrusted = false;
+
+        let mut stale: Vec<PathBuf> = self.stale.drain().filter(|path| is_under(path, &trusted)).collect();
+        stale.sort();
+        for path in stale {
+            let relative = match relative_path(&root, &path) {
+                Some(relative) => relative,
+                None => continue,
+            };
+            // Deleted or renamed lanes take every card inside them along
+            let removed: Vec<String> = self.docs.keys()
+                .filter(|doc| *doc == &relative || doc.starts_with(&format!("{}/", relative)))
+                .cloned()
+                .collect();
+            for doc in removed {
+                self.remove(&doc);
+            }
+            if path.is_dir() {
+                self.sync_tree(&root, &path, &[], &mut HashSet::new(), &mut HashSet::new());
+            } else if path.is_file() {
+                self.index_file(&root, &path);
+            }
+        }
+
+        if trusted.contains(&root) {
+            return;
+        }
+        let mut seen = HashSet::new();
+        self.sync_tree(&root, &root, &trusted, &mut seen, &mut HashSet::new());
+        let removed: Vec<String> = self.docs.keys()
+            .filter(|doc| !seen.contains(*doc) && !is_under(&root.join(doc.trim_start_matches('/')), &trusted))
+            .cloned()
+            .collect();
+        for doc in removed {
+            self.remove(&doc);
+        }
+    }
+
+    // Indexes the cards below `dir` that are new or changed on disk, skipping
+    // the `trusted` subtrees, and collects the path of every entry in `seen`
+    fn sync_tree(&mut self, root: &Path, dir: &Path, trusted: &[PathBuf], seen: &mut HashSet<String>, visited: &mut HashSet<PathBuf>) {
+        // Symlinked lanes pointing back up the tree would otherwise never end
+        if !fs::canonicalize(dir).is_ok_and(|canonical| visited.insert(canonical)) {
+            return;
+        }
+
+        for entry in fs::read_dir(dir).into_iter().flatten().flatten() {
+            let path = entry.path();
+            let relative = match relative_path(root, &path) {
+                Some(relative) => relative,
+                None => continue,
+            };
+            if is_under(&path, trusted) {
+                continue;
+            }
+            let metadata = match fs::metadata(&path) {
+                Ok(metadata) => metadata,
+                Err(_) => continue,
+            };
+            if metadata.is_dir() {
+                self.sync_tree(root, &path, trusted, seen, visited);
+                continue;
+            }
+
+            let unchanged = self.docs.get(&relative)
+                .is_some_and(|doc| doc.modified == metadata.modified().ok() && doc.size == metadata.len());
+            seen.insert(relative);
+            if !unchanged {
+                self.index_file(root, &path);
+            }
+        }
+    }
+
+    fn index_file(&mut self, root: &Path, path: &Path) {
+        let relative = match relative_path(root, path) {
+            Some(relative) => relative,
+            None => return,
+        };
+        let name = match relative.strip_suffix(".md") {
+            Some(stem) => stem.rsplit('/').next().unwrap_or_default().to_string(),
+            None => return,
+        };
+        // Cards live in a lane, files at the root of a board are not cards
+        let mut parts: Vec<&str> = relative.trim_start_matches('/').split('/').collect();
+        if parts.len() < 2 {
+            return;
+        }
+        parts.pop();
+        let lane = parts.pop().unwrap_or_default().to_string();
+        let board: String = parts.iter().map(|part| format!("/{}", part)).collect();
+
+        let (content, metadata) = match fs::read_to_string(path).and_then(|content| Ok((content, fs::metadata(path)?))) {
+            Ok(read) => read,
+            Err(_) => return,
+        };
+
+        self.remove(&relative);
+
+        let name_terms: HashSet<String> = tokenize(&name).into_iter().collect();
+        let mut frequencies: HashMap<String, u32> = HashMap::new();
+        let mut length = 0;
+        for term in tokenize(&content).into_iter().chain(name_terms.iter().cloned()) {
+            *frequencies.entry(term).or_default() += 1;
+            length += 1;
+        }
+        for (term, frequency) in frequencies {
+            self.postings.entry(term).or_default().insert(relative.clone(), frequency);
+        }
+
+        self.total_length += length;
+        self.docs.insert(relative, Doc {
+            board,
+            lane,
+            name,
+            content,
+            name_terms,
+            length,
+            modified: metadata.modified().ok(),
+            size: metadata.len(),
+        });
+    }
+
+    fn remove(&mut self, relative: &str) {
+        let doc = match self.docs.remove(relative) {
+            Some(doc) => doc,
+            None => return,
+        };
+        self.total_length -= doc.length;
+
+        let terms: Vec<String> = tokenize(&doc.content).into_iter().chain(doc.name_terms).collect::<HashSet<_>>().into_iter().collect();
+        for term in terms {
+            if let Some(docs) = self.postings.get_mut(&term) {
+                docs.remove(relative);
+                if docs.is_empty() {
+                    self.postings.remove(&term);
+                }
+            }
+        }
+    }
+
+    // BM25 score of every document matching all `terms`
+    fn score(&self, terms: &[String]) -> Vec<(String, f64)> {
+        let doc_count = self.docs.len() as f64;
+        let average_length = (self.total_length as f64 / doc_count.max(1.0)).max(1.0);
+        let mut scores: Option<HashMap<String, f64>> = None;
+
+        for term in terms {
+            // Occurrences of the term in each document, words it only starts counting for less
+            let mut frequencies: HashMap<&String, f64> = HashMap::new();
+            for (indexed, docs) in self.postings.range(term.clone()..).take_while(|(indexed, _)| indexed.starts_with(term.as_str())) {
+                let weight = if indexed == term { 1.0 } else { PREFIX_WEIGHT };
+                for (path, &frequency) in docs {
+                    let mut frequency = frequency as f64;
+                    if self.docs[path].name_terms.contains(indexed) {
+                        frequency += NAME_BOOST;
+                    }
+                    *frequencies.entry(path).or_default() += weight * frequency;
+                }
+            }
+
+            let matching = frequencies.len() as f64;
+            let idf = ((doc_count - matching + 0.5) / (matching + 0.5) + 1.0).ln();
+            let term_scores: HashMap<String, f64> = frequencies.into_iter()
+                .map(|(path, frequency)| {
+                    let normalization = K1 * (1.0 - B + B * self.docs[path].length as f64 / average_length);
+                    (path.clone(), idf * frequency * (K1 + 1.0) / (frequency + normalization))
+                })
+                .collect();
+
+            scores = Some(match scores {
+                None => term_scores,
+                Some(scores) => scores.into_iter()
+                    .filter_map(|(path, score)| term_scores.get(&path).map(|term_score| (path, score + term_score)))
+                    .collect(),
+            });
+        }
+
+        scores.unwrap_or_default().into_iter().collect()
+    }
+}
+
+fn is_under(path: &Path, roots: &[PathBuf]) -> bool {
+    roots.iter().any(|root| path.starts_with(root))
+}
+
+// Frontend path of an entry under `root`, or `None` for hidden entries such
+// as the trash and editor leftovers like `card.md~`
+fn relative_path(root: &Path, path: &Path) -> Option<String> {
+    let relative = path.strip_prefix(root).ok()?;
+    let mut result = String::new();
+    for component in relative.components() {
+        if let Component::Normal(part) = component {
+            let part = part.to_string_lossy();
+            if part.starts_with('.') || part.ends_with('~') {
+                return None;
+            }
+            result.push('/');
+            result.push_str(&part);
+        }
+    }
+    Some(result)
+}
+
+// Lowercased words made of letters and digits
+fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_lowercase())
+        .collect()
+}
+
+fn snippet(content: &str, terms: &[String]) -> Vec<SnippetPart> {
+    // Words of the content with their byte ranges
+    let mut words = Vec::new();
+    let mut start = None;
+    for (i, c) in content.char_indices().chain(std::iter::once((content.len(), ' '))) {
+        match (c.is_alphanumeric(), start) {
+            (true, None) => start = Some(i),
+            (false, Some(word_start)) => {
+                words.push(word_start..i);
+                start = None;
+            }
+            _ => {}
+        }
+    }
+
+    let matches: Vec<&std::ops::Range<usize>> = words.iter()
+        .filter(|word| {
+            let word = content[(*word).clone()].to_lowercase();
+            terms.iter().any(|term| word.starts_with(term.as_str()))
+        })
+        .collect();
+
+    // Cards matched by name only show their beginning
+    let first = matches.first().map_or(0, |word| word.start);
+    let from = floor_char_boundary(content, first.saturating_sub(SNIPPET_CONTEXT));
+    let to = floor_char_boundary(content, (first + SNIPPET_CONTEXT * 2).min(content.len()));
+
+    let mut parts = Vec::new();
+    let mut position = from;
+    for word in matches.into_iter().filter(|word| word.start >= from && word.end <= to) {
+        if word.start > position {
+            parts.push(SnippetPart { text: content[position..word.start].to_string(), highlight: false });
+        }
+        parts.push(SnippetPart { text: content[word.clone()].to_string(), highlight: true });
+        position = word.end;
+    }
+    if position < to {
+        parts.push(SnippetPart { text: content[position..to].to_string(), highlight: false });
+    }
+    if let Some(first) = parts.first_mut() {
+        if from > 0 {
+            first.text = format!("…{}", first.text.trim_start());
+        }
+    }
+    if let Some(last) = parts.last_mut() {
+        if to < content.len() {
+            last.text = format!("{}…", last.text.trim_end());
+        }
+    }
+
+    parts
+}
+
+fn floor_char_boundary(text: &str, mut index: usize) -> usize {
+    while !text.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+    use notify::event::{CreateKind, ModifyKind, RemoveKind};
+
+    fn paths(hits: &[SearchHit]) -> Vec<&str> {
+        hits.iter().map(|hit| hit.path.as_str()).collect()
+    }
+
+    fn snippet_text(parts: &[SnippetPart]) -> String {
+        parts.iter().map(|part| part.text.as_str()).collect()
+    }
+
+    fn highlights(parts: &[SnippetPart]) -> Vec<&str> {
+        parts.iter().filter(|part| part.highlight).map(|part| part.text.as_str()).collect()
+    }
+
+    #[test]
+    fn ranks_name_matches_then_exact_words_first() {
+        let dir = TempDir::new("search-ranking");
+        dir.write("board/lane/Release notes.md", "Draft for the next version");
+        dir.write("board/lane/Planning.md", "Write the release announcement");
+        dir.write("board/lane/Backlog.md", "Releases are monthly");
+        dir.write("board/lane/Unrelated.md", "Nothing to see here");
+        dir.write("notes.md", "A release outside any lane");
+        let index = SearchIndex::default();
+
+        let hits = index.search(dir.path(), &[], "release", None, 10);
+        assert_eq!(paths(&hits), vec!["/board/lane/Release notes.md", "/board/lane/Planning.md", "/board/lane/Backlog.md"]);
+        assert!(hits.windows(2).all(|pair| pair[0].score >= pair[1].score));
+        assert_eq!((hits[0].board.as_str(), hits[0].lane.as_str(), hits[0].card.as_str()), ("/board", "lane", "Release notes"));
+
+        // Every term has to match
+        assert_eq!(paths(&index.search(dir.path(), &[], "release announcement", None, 10)), vec!["/board/lane/Planning.md"]);
+        assert!(index.search(dir.path(), &[], "release missing", None, 10).is_empty());
+        assert_eq!(index.search(dir.path(), &[], "release", None, 1).len(), 1);
+    }
+
+    #[test]
+    fn restricts_hits_to_a_board_and_its_sub_boards() {
+        let dir = TempDir::new("search-board");
+        dir.write("work/lane/a.md", "shared term");
+        dir.write("work/team/lane/b.md", "shared term");
+        dir.write("workshop/lane/c.md", "shared term");
+        let index = SearchIndex::default();
+
+        let hits = index.search(dir.path(), &[], "shared", Some("/work/"), 10);
+        assert_eq!(paths(&hits), vec!["/work/lane/a.md", "/work/team/lane/b.md"]);
+    }
+
+    #[test]
+    fn cuts_snippets_on_character_boundaries() {
+        let content = format!("{} needle {}", "é".repeat(100), "😀".repeat(100));
+        let parts = snippet(&content, &["needle".to_string()]);
+        let text = snippet_text(&parts);
+        assert_eq!(highlights(&parts), vec!["needle"]);
+        assert!(text.starts_with('…') && text.ends_with('…'));
+        assert!(content.contains(text.trim_matches('…')));
+
+        // Odd offsets land in the middle of the two byte characters
+        let content = format!("a {} wörd {}", "ü".repeat(SNIPPET_CONTEXT), "ß".repeat(SNIPPET_CONTEXT * 2));
+        let parts = snippet(&content, &["wörd".to_string()]);
+        assert_eq!(highlights(&parts), vec!["wörd"]);
+        assert!(content.contains(snippet_text(&parts).trim_matches('…')));
+    }
+
+    #[test]
+    fn shows_the_beginning_of_cards_matched_by_name() {
+        let parts = snippet("Short content", &["title".to_string()]);
+        assert!(highlights(&parts).is_empty());
+        assert_eq!(snippet_text(&parts), "Short content");
+        assert!(snippet("", &["title".to_string()]).is_empty());
+    }
+
+    #[test]
+    fn refreshes_marked_paths_only_while_watched() {
+        let dir = TempDir::new("search-refresh");
+        let card = dir.write("board/lane/card.md", "original words");
+        let index = SearchIndex::default();
+        let marker = index.marker();
+        let watched = vec![(dir.path().to_path_buf(), 1)];
+        assert_eq!(index.search(dir.path(), &watched, "original", None, 10).len(), 1);
+
+        // The watcher running since the last search reported this change
+        fs::write(&card, "updated words").unwrap();
+        marker.mark(&Ok(Event::new(EventKind::Modify(ModifyKind::Any)).add_path(card.clone())));
+        assert!(index.search(dir.path(), &watched, "original", None, 10).is_empty());
+        assert_eq!(index.search(dir.path(), &watched, "updated", None, 10).len(), 1);
+
+        // Unmarked changes under a trusted watcher are not looked for
+        let other = dir.write("board/lane/other.md", "updated too");
+        assert_eq!(index.search(dir.path(), &watched, "updated", None, 10).len(), 1);
+        marker.mark(&Ok(Event::new(EventKind::Create(CreateKind::File)).add_path(other.clone())));
+        assert_eq!(index.search(dir.path(), &watched, "updated", None, 10).len(), 2);
+
+        // Removed lanes take their cards along
+        fs::remove_dir_all(dir.path().join("board/lane")).unwrap();
+        marker.mark(&Ok(Event::new(EventKind::Remove(RemoveKind::Folder)).add_path(dir.path().join("board/lane"))));
+        assert!(index.search(dir.path(), &watched, "updated", None, 10).is_empty());
+    }
+
+    #[test]
+    fn rescans_when_the_watchers_changed_or_lost_events() {
+        let dir = TempDir::new("search-rescan");
+        dir.write("board/lane/card.md", "first");
+        let index = SearchIndex::default();
+        let watched = vec![(dir.path().to_path_buf(), 1)];
+        index.search(dir.path(), &watched, "first", None, 10);
+
+        // A watcher started since the last search may have missed changes
+        dir.write("board/lane/new.md", "second");
+        let restarted = vec![(dir.path().to_path_buf(), 2)];
+        assert_eq!(index.search(dir.path(), &restarted, "second", None, 10).len(), 1);
+
+        dir.write("board/lane/lost.md", "third");
+        index.marker().mark(&Err(notify::Error::generic("events lost")));
+        assert_eq!(index.search(dir.path(), &restarted, "third", None, 10).len(), 1);
+    }
+}
@@ -0,0 +1,317 @@
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+// Writes `contents` to a hidden temporary file next to `path`, syncs it and
+// renames it over `path`, so readers only ever see the old or the new content.
+// Symlinks are written through, keeping the link in place.
+pub fn write_atomic(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let path = if fs::symlink_metadata(path).map(|m| m.file_type().is_symlink()).unwrap_or(false) {
+        fs::canonicalize(path)?
+    } else {
+        path.to_path_buf()
+    };
+
+    let dir = path.parent().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "path has no parent directory"))?;
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    // Hidden, so neither the board listing nor the file watcher pick it up
+    let temp_path = dir.join(format!(".{}.{}.tmp", file_name, Uuid::new_v4()));
+
+    let result = write_and_rename(&temp_path, &path, contents).and_then(|_| sync_dir(dir));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result
+}
+
+fn write_and_rename(temp_path: &Path, path: &Path, contents: &[u8]) -> io::Result<()> {
+    let mut file = File::create(temp_path)?;
+    if let Ok(metadata) = fs::metadata(path) {
+        file.set_permissions(metadata.permissions())?;
+    }
+    file.write_all(contents)?;
+    file.sync_all()?;
+    drop(file);
+
+    fs::rename(temp_path, path)
+}
+
+// Makes the rename itself durable
+#[cfg(unix)]
+fn sync_dir(dir: &Path) -> io::Result<()> {
+    File::open(dir)?.sync_all()
+}
+
+#[cfg(not(unix))]
+fn sync_dir(_dir: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+// What to do when a card or lane is created or renamed onto a name that is
+// already taken
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum OnConflict {
+    // Fail with `AlreadyExists`
+    #[default]
+    Fail,
+    // Use the first free name out of "Card (2).md", "Card (3).md", ...
+    Suffix,
+}
+
+// Upper bound on the names tried by `OnConflict::Suffix`
+const MAX_SUFFIX: u32 = 1000;
+
+// Runs `claim` on `path`, and with `OnConflict::Suffix` on numbered variants
+// of it for as long as it fails with `AlreadyExists`. Returns the path that
+// was claimed.
+pub fn claim_name<F>(path: &Path, on_conflict: OnConflict, mut claim: F) -> io::Result<PathBuf>
+where
+    F: FnMut(&Path) -> io::Result<()>,
+{
+    let error = match claim(path) {
+        Ok(()) => return Ok(path.to_path_buf()),
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists && on_conflict == OnConflict::Suffix => e,
+        Err(e) => return Err(e),
+    };
+
+    for number in 2..=MAX_SUFFIX {
+        let candidate = numbered(path, number);
+        match claim(&candidate) {
+            Ok(()) => return Ok(candidate),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+            Err(e) => return Err(e),
+        }
+    }
+
+    Err(error)
+}
+
+// "Lane/Card.md" becomes "Lane/Card (2).md", "Lane" becomes "Lane (2)"
+fn numbered(path: &Path, number: u32) -> PathBuf {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let (stem, extension) = match name.strip_suffix(".md") {
+        Some(stem) => (stem, ".md"),
+        None => (name.as_ref(), ""),
+    };
+    path.with_file_name(format!("{} ({}){}", stem, number, extension))
+}
+
+// Creates a card, failing with `AlreadyExists` instead of replacing an
+// existing one. The name is claimed with an exclusive create before the
+// content is written, so of two concurrent creators only one succeeds.
+pub fn create_new(path: &Path, contents: &[u8]) -> io::Result<()> {
+    OpenOptions::new().write(true).create_new(true).open(path)?;
+
+    let result = write_atomic(path, contents);
+    if result.is_err() {
+        let _ = fs::remove_file(path);
+    }
+    result
+}
+
+// Renames `from` to `to`, failing with `AlreadyExists` when `to` is taken,
+// where a plain rename would silently replace it. Files are moved by hard
+// linking, which fails atomically on an existing destination; filesystems
+// without hard links and directories fall back to checking first.
+pub fn rename_new(from: &Path, to: &Path) -> io::Result<()> {
+    // Changing only the case of a name on a case-insensitive filesystem
+    if is_same_entry(from, to) {
+        return fs::rename(from, to);
+    }
+
+    if fs::symlink_metadata(from)?.is_file() {
+        match fs::hard_link(from, to) {
+            Ok(()) => return fs::remove_file(from),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => return Err(e),
+            Err(_) => {}
+        }
+    }
+
+    if fs::symlink_metadata(to).is_ok() {
+        return Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("{} already exists", to.display())));
+    }
+    fs::rename(from, to)
+}
+
+// Canonical paths only match for two spellings of the same entry on
+// case-insensitive filesystems. Symlinks are never treated as the same entry
+// as their target.
+fn is_same_entry(a: &Path, b: &Path) -> bool {
+    let is_regular = |path: &Path| fs::symlink_metadata(path).map(|m| !m.file_type().is_symlink()).unwrap_or(false);
+    if !is_regular(a) || !is_regular(b) {
+        return false;
+    }
+    matches!((fs::canonicalize(a), fs::canonicalize(b)), (Ok(a), Ok(b)) if a == b)
+}
+
+// Reads a JSON object config file such as `tags.json` or `sort.json`. A
+// missing file is an empty config; a corrupted one is set aside and replaced
+// by the last good copy kept by `write_config`. When no good copy exists the
+// error is returned, so callers never overwrite the file with an empty object.
+pub fn read_config(path: &Path) -> io::Result<Map<String, Value>> {
+    let error = match read_object(path) {
+        Ok(config) => return Ok(config.unwrap_or_default()),
+        Err(e) => e,
+    };
+
+    let backup = match read_object(&backup_path(path)) {
+        Ok(Some(backup)) => backup,
+        _ => return Err(error),
+    };
+
+    log::warn!("{} could not be parsed ({}), restoring the last good copy", path.display(), error);
+    let _ = fs::rename(path, suffixed(path, "corrupt"));
+    write_atomic(path, &serialize(&backup)?)?;
+
+    Ok(backup)
+}
+
+// Atomically replaces a JSON config file and refreshes its last good copy
+pub fn write_config(path: &Path, config: &Map<String, Value>) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let contents = serialize(config)?;
+    write_atomic(path, &contents)?;
+    write_atomic(&backup_path(path), &contents)
+}
+
+fn read_object(path: &Path) -> io::Result<Option<Map<String, Value>>> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+
+    serde_json::from_str::<Map<String, Value>>(&content)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+fn serialize(config: &Map<String, Value>) -> io::Result<Vec<u8>> {
+    serde_json::to_vec(config).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+fn backup_path(path: &Path) -> PathBuf {
+    suffixed(path, "bak")
+}
+
+fn suffixed(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".{}", suffix));
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+    use serde_json::json;
+
+    fn names(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(dir).unwrap().map(|entry| entry.unwrap().file_name().to_string_lossy().to_string()).collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn writes_atomically_without_leaving_temporary_files() {
+        let dir = TempDir::new("storage-write");
+        let path = dir.path().join("card.md");
+        write_atomic(&path, b"first").unwrap();
+        write_atomic(&path, b"second").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "second");
+        assert_eq!(names(dir.path()), vec!["card.md"]);
+
+        // Renaming over a directory fails after the temporary file is written
+        fs::create_dir(dir.path().join("lane")).unwrap();
+        assert!(write_atomic(&dir.path().join("lane"), b"content").is_err());
+        assert_eq!(names(dir.path()), vec!["card.md", "lane"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn writes_through_symlinks() {
+        let dir = TempDir::new("storage-symlink");
+        let target = dir.write("target.md", "old");
+        let link = dir.path().join("link.md");
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+
+        write_atomic(&link, b"new").unwrap();
+        assert!(fs::symlink_metadata(&link).unwrap().file_type().is_symlink());
+        assert_eq!(fs::read_to_string(&target).unwrap(), "new");
+    }
+
+    #[test]
+    fn recovers_corrupted_config_from_the_last_good_copy() {
+        let dir = TempDir::new("storage-config");
+        let path = dir.path().join("tags.json");
+        assert!(read_config(&path).unwrap().is_empty());
+
+        let config = json!({ "/board": { "urgent": "red" } }).as_object().unwrap().clone();
+        write_config(&path, &config).unwrap();
+        assert_eq!(read_config(&path).unwrap(), config);
+
+        fs::write(&path, "{ \"/board\": ").unwrap();
+        assert_eq!(read_config(&path).unwrap(), config);
+        assert_eq!(read_object(&path).unwrap(), Some(config));
+        assert_eq!(fs::read_to_string(dir.path().join("tags.json.corrupt")).unwrap(), "{ \"/board\": ");
+    }
+
+    #[test]
+    fn keeps_corrupted_config_without_a_good_copy() {
+        let dir = TempDir::new("storage-config-no-backup");
+        let path = dir.write("sort.json", "[1, 2");
+
+        let error = read_config(&path).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "[1, 2");
+        assert_eq!(names(dir.path()), vec!["sort.json"]);
+    }
+
+    #[test]
+    fn claims_numbered_names_on_conflict() {
+        let dir = TempDir::new("storage-claim");
+        dir.write("lane/Card.md", "");
+        dir.write("lane/Card (2).md", "");
+        fs::create_dir(dir.path().join("Lane")).unwrap();
+        let create = |path: &Path| create_new(path, b"new");
+
+        let error = claim_name(&dir.path().join("lane/Card.md"), OnConflict::Fail, create).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::AlreadyExists);
+
+        let claimed = claim_name(&dir.path().join("lane/Card.md"), OnConflict::Suffix, create).unwrap();
+        assert_eq!(claimed, dir.path().join("lane/Card (3).md"));
+        assert_eq!(fs::read_to_string(&claimed).unwrap(), "new");
+        assert_eq!(fs::read_to_string(dir.path().join("lane/Card.md")).unwrap(), "");
+
+        let claimed = claim_name(&dir.path().join("Lane"), OnConflict::Suffix, |path| fs::create_dir(path)).unwrap();
+        assert_eq!(claimed, dir.path().join("Lane (2)"));
+    }
+
+    #[test]
+    fn renames_without_replacing_existing_entries() {
+        let dir = TempDir::new("storage-rename");
+        let from = dir.write("lane/from.md", "from");
+        let taken = dir.write("lane/taken.md", "taken");
+        dir.write("other/card.md", "");
+
+        let error = rename_new(&from, &taken).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(fs::read_to_string(&from).unwrap(), "from");
+        assert_eq!(fs::read_to_string(&taken).unwrap(), "taken");
+
+        let error = rename_new(&dir.path().join("lane"), &dir.path().join("other")).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::AlreadyExists);
+
+        rename_new(&from, &dir.path().join("lane/to.md")).unwrap();
+        assert_eq!(names(&dir.path().join("lane")), vec!["taken.md", "to.md"]);
+        rename_new(&dir.path().join("lane"), &dir.path().join("renamed")).unwrap();
+        assert!(dir.path().join("renamed/to.md").is_file());
+    }
+}
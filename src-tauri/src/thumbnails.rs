@@ -0,0 +1,221 @@
+use crate::images::ImageFormat;
+use crate::storage;
+use image::imageops::FilterType;
+use image::ImageReader;
+use std::fs;
+use std::io::{self, Cursor};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::sync::Mutex;
+use std::thread;
+
+// Thumbnails are cached in a hidden directory of the images one, which the
+// clean-up, the migration and the image scheme leave alone, as
+// `<image name>@<size>.<extension>`
+const DIRECTORY: &str = ".thumbnails";
+// Uploads waiting for their thumbnails. Past that, new ones get theirs when
+// first shown instead.
+const QUEUE_SIZE: usize = 64;
+
+// Sizes are the longest side in pixels, smallest first, and an empty list
+// disables thumbnails. Only PNG and WebP are written.
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub sizes: Vec<u32>,
+    pub format: ImageFormat,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config { sizes: vec![256, 1024], format: ImageFormat::Webp }
+    }
+}
+
+impl Config {
+    // The smallest configured size at least as large as `requested`, or the
+    // largest one, so arbitrary sizes cannot fill the cache
+    pub fn size_for(&self, requested: u32) -> Option<u32> {
+        self.sizes.iter().copied().find(|size| *size >= requested).or_else(|| self.sizes.last().copied())
+    }
+}
+
+// Parses `LOCAL_IMAGES_THUMBNAIL_SIZES`, e.g. "256,1024"
+pub fn parse_sizes(value: &str) -> Vec<u32> {
+    let mut sizes: Vec<u32> = value.split(',').filter_map(|size| size.trim().parse().ok()).filter(|size| *size > 0).collect();
+    sizes.sort_unstable();
+    sizes.dedup();
+    sizes
+}
+
+// The thumbnail of `original` fitting in `size` pixels, made when it is
+// missing or older than the original. `None` when the original is no larger
+// than that, or cannot be decoded, and is to be shown as is.
+pub fn get(images_dir: &Path, original: &Path, original_format: ImageFormat, size: u32, format: ImageFormat) -> Option<PathBuf> {
+    // AVIF decoding needs a native library, such images are always shown in full
+    if original_format == ImageFormat::Avif {
+        return None;
+    }
+    let name = original.file_name()?.to_string_lossy();
+    let path = images_dir.join(DIRECTORY).join(format!("{}@{}.{}", name, size, format.extension()));
+
+    let modified = |path: &Path| fs::metadata(path).and_then(|metadata| metadata.modified()).ok();
+    if modified(&path).is_some_and(|thumbnail| Some(thumbnail) >= modified(original)) {
+        return Some(path);
+    }
+
+    match generate(original, &path, size, format) {
+        Ok(true) => Some(path),
+        Ok(false) => None,
+        Err(e) => {
+            log::warn!("Failed to make a thumbnail of {}: {}", original.display(), e);
+            None
+        }
+    }
+}
+
+// Makes every configured thumbnail of new uploads on a single background
+// thread, so the first board showing them does not wait for them and a burst
+// of uploads does not decode every image at once
+pub struct Queue {
+    sender: Mutex<SyncSender<Upload>>,
+}
+
+struct Upload {
+    images_dir: PathBuf,
+    name: String,
+    format: ImageFormat,
+}
+
+impl Queue {
+    pub fn start(config: Config) -> Self {
+        let (sender, receiver) = mpsc::sync_channel(QUEUE_SIZE);
+        thread::spawn(move || prepare_loop(config, receiver));
+        Queue { sender: Mutex::new(sender) }
+    }
+
+    pub fn prepare(&self, images_dir: PathBuf, name: String, format: ImageFormat) {
+        let _ = self.sender.lock().unwrap().try_send(Upload { images_dir, name, format });
+    }
+}
+
+fn prepare_loop(config: Config, receiver: Receiver<Upload>) {
+    while let Ok(upload) = receiver.recv() {
+        let original = upload.images_dir.join(&upload.name);
+        for size in &config.sizes {
+            get(&upload.images_dir, &original, upload.format, *size, config.format);
+        }
+    }
+}
+
+// Removes the thumbnails whose original is gone
+pub fn prune(images_dir: &Path) -> io::Result<()> {
+    let entries = match fs::read_dir(images_dir.join(DIRECTORY)) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+
+    for entry in entries {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        let original = match name.rsplit_once('@') {
+            Some((original, _)) => original.to_string(),
+            None => continue,
+        };
+        if !images_dir.join(original).is_file() {
+            fs::remove_file(entry.path())?;
+        }
+    }
+
+    Ok(())
+}
+
+// Writes the thumbnail, returning false when the original already fits
+fn generate(original: &Path, path: &Path, size: u32, format: ImageFormat) -> Result<bool, image::ImageError> {
+    let reader = ImageReader::open(original)?.with_guessed_format()?;
+    let (width, height) = reader.into_dimensions()?;
+    if width <= size && height <= size {
+        return Ok(false);
+    }
+
+    let image = ImageReader::open(original)?.with_guessed_format()?.decode()?;
+    let thumbnail = image.resize(size, size, FilterType::Triangle);
+    let mut content = Cursor::new(Vec::new());
+    let encoding = match format {
+        ImageFormat::Png => image::ImageFormat::Png,
+        _ => image::ImageFormat::WebP,
+    };
+    thumbnail.write_to(&mut content, encoding)?;
+
+    fs::create_dir_all(path.parent().unwrap_or(path))?;
+    storage::write_atomic(path, content.get_ref())?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+    use image::{DynamicImage, RgbImage};
+
+    fn write_png(path: &Path, width: u32, height: u32) {
+        DynamicImage::ImageRgb8(RgbImage::new(width, height)).save_with_format(path, image::ImageFormat::Png).unwrap();
+    }
+
+    #[test]
+    fn parses_sizes() {
+        assert_eq!(parse_sizes("256,1024"), vec![256, 1024]);
+        assert_eq!(parse_sizes(" 1024 , 256, 256 "), vec![256, 1024]);
+        assert_eq!(parse_sizes("0,-1,big,512"), vec![512]);
+        assert!(parse_sizes("").is_empty());
+    }
+
+    #[test]
+    fn rounds_requested_sizes_to_configured_ones() {
+        let config = Config { sizes: vec![256, 1024], format: ImageFormat::Webp };
+        assert_eq!(config.size_for(1), Some(256));
+        assert_eq!(config.size_for(256), Some(256));
+        assert_eq!(config.size_for(257), Some(1024));
+        assert_eq!(config.size_for(5000), Some(1024));
+        assert_eq!(Config { sizes: Vec::new(), format: ImageFormat::Webp }.size_for(256), None);
+    }
+
+    #[test]
+    fn makes_and_reuses_thumbnails() {
+        let dir = TempDir::new("thumbnails-get");
+        let original = dir.path().join("wide.png");
+        write_png(&original, 600, 300);
+
+        let path = get(dir.path(), &original, ImageFormat::Png, 256, ImageFormat::Png).unwrap();
+        assert_eq!(path, dir.path().join(".thumbnails/wide.png@256.png"));
+        assert_eq!(image::image_dimensions(&path).unwrap(), (256, 128));
+        let modified = fs::metadata(&path).unwrap().modified().unwrap();
+        assert_eq!(get(dir.path(), &original, ImageFormat::Png, 256, ImageFormat::Png), Some(path.clone()));
+        assert_eq!(fs::metadata(&path).unwrap().modified().unwrap(), modified);
+
+        // Images already small enough, and ones that do not decode, are shown as is
+        assert_eq!(get(dir.path(), &original, ImageFormat::Png, 1024, ImageFormat::Png), None);
+        let broken = dir.write("broken.png", b"\x89PNG\r\n\x1a\n");
+        assert_eq!(get(dir.path(), &broken, ImageFormat::Png, 256, ImageFormat::Png), None);
+    }
+
+    #[test]
+    fn prunes_thumbnails_of_removed_images() {
+        let dir = TempDir::new("thumbnails-prune");
+        dir.write("kept.png", "");
+        dir.write(".thumbnails/kept.png@256.webp", "");
+        dir.write(".thumbnails/removed.png@256.webp", "");
+        dir.write(".thumbnails/removed.png@1024.webp", "");
+        dir.write(".thumbnails/unrelated", "");
+
+        prune(dir.path()).unwrap();
+        let mut names: Vec<_> = fs::read_dir(dir.path().join(DIRECTORY)).unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        names.sort();
+        assert_eq!(names, vec!["kept.png@256.webp", "unrelated"]);
+
+        let empty = TempDir::new("thumbnails-prune-empty");
+        prune(empty.path()).unwrap();
+    }
+}
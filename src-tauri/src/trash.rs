@@ -0,0 +1,229 @@
+use crate::error::{CommandError, ErrorCode};
+use crate::paths;
+use crate::storage;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use uuid::Uuid;
+
+// Lives inside the tasks directory, so moving a card or lane there is a
+// cheap rename on the same filesystem. Being hidden, it is skipped by
+// `get_resource` and by the file watcher.
+const TRASH_DIR: &str = ".trash";
+const ENTRY_FILE: &str = "entry.json";
+
+// A deleted card or lane, stored as `.trash/<id>/<original name>` next to
+// an `entry.json` holding this metadata
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct TrashEntry {
+    pub id: String,
+    pub original_path: String,
+    pub is_dir: bool,
+    pub deleted_at: SystemTime,
+}
+
+// Moves `full_path` into the trash of `tasks_root`
+pub fn trash(tasks_root: &Path, full_path: &Path) -> io::Result<TrashEntry> {
+    if full_path.starts_with(tasks_root.join(TRASH_DIR)) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "trash entries can only be purged"));
+    }
+
+    let metadata = fs::symlink_metadata(full_path)?;
+    let name = full_path.file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "nothing to delete"))?;
+
+    let entry = TrashEntry {
+        id: Uuid::new_v4().to_string(),
+        original_path: paths::to_relative(tasks_root, full_path),
+        is_dir: metadata.is_dir(),
+        deleted_at: SystemTime::now(),
+    };
+
+    // The metadata is written first, so an item in the trash can always be
+    // listed and restored, and the entry is dropped if the move fails
+    let entry_dir = tasks_root.join(TRASH_DIR).join(&entry.id);
+    fs::create_dir_all(&entry_dir)?;
+    let moved = write_entry(&entry_dir, &entry).and_then(|_| move_path(full_path, &entry_dir.join(name)));
+    if let Err(e) = moved {
+        let _ = fs::remove_dir_all(&entry_dir);
+        return Err(e);
+    }
+
+    Ok(entry)
+}
+
+// Most recently deleted first
+pub fn list(tasks_root: &Path) -> io::Result<Vec<TrashEntry>> {
+    let trash_dir = tasks_root.join(TRASH_DIR);
+    let mut entries = Vec::new();
+
+    let dirs = match fs::read_dir(&trash_dir) {
+        Ok(dirs) => dirs,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(entries),
+        Err(e) => return Err(e),
+    };
+
+    for dir in dirs.flatten() {
+        // Entries whose metadata cannot be read are left for `purge` to clean up
+        if let Ok(entry) = read_entry(&dir.path()) {
+            entries.push(entry);
+        }
+    }
+
+    entries.sort_by_key(|entry| std::cmp::Reverse(entry.deleted_at));
+    Ok(entries)
+}
+
+// Moves an entry back to where it was deleted from. Fails if something now
+// exists at that path.
+pub fn restore(tasks_root: &Path, id: &str) -> Result<TrashEntry, CommandError> {
+    let entry_dir = entry_dir(tasks_root, id)?;
+    let entry = read_entry(&entry_dir)?;
+    let original_path = entry.original_path.as_str();
+    let destination = paths::resolve_entry(tasks_root, original_path)?;
+
+    if fs::symlink_metadata(&destination).is_ok() {
+        return Err(CommandError::already_exists(original_path));
+    }
+
+    let name = destination.file_name().unwrap_or_default();
+    if let Some(parent) = destination.parent() {
+        fs::create_dir_all(parent).map_err(|e| CommandError::io(e, original_path))?;
+    }
+    move_path(&entry_dir.join(name), &destination).map_err(|e| CommandError::io(e, original_path))?;
+    fs::remove_dir_all(&entry_dir)?;
+
+    Ok(entry)
+}
+
+// Permanently removes one entry, or the whole trash when `id` is `None`
+pub fn purge(tasks_root: &Path, id: Option<&str>) -> Result<(), CommandError> {
+    match id {
+        Some(id) => Ok(fs::remove_dir_all(entry_dir(tasks_root, id)?)?),
+        None => match fs::remove_dir_all(tasks_root.join(TRASH_DIR)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        },
+    }
+}
+
+// Removes entries deleted more than `retention` ago
+pub fn purge_expired(tasks_root: &Path, retention: Duration) -> io::Result<()> {
+    let now = SystemTime::now();
+
+    for entry in list(tasks_root)? {
+        let age = now.duration_since(entry.deleted_at).unwrap_or_default();
+        if age > retention {
+            fs::remove_dir_all(tasks_root.join(TRASH_DIR).join(&entry.id))?;
+        }
+    }
+
+    Ok(())
+}
+
+fn entry_dir(tasks_root: &Path, id: &str) -> Result<PathBuf, CommandError> {
+    if id.is_empty() || !id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+        return Err(CommandError::new(ErrorCode::InvalidPath, format!("Invalid trash entry: {}", id)));
+    }
+
+    let entry_dir = tasks_root.join(TRASH_DIR).join(id);
+    if !entry_dir.is_dir() {
+        return Err(CommandError::new(ErrorCode::NotFound, "This item is no longer in the trash"));
+    }
+    Ok(entry_dir)
+}
+
+fn read_entry(entry_dir: &Path) -> io::Result<TrashEntry> {
+    let content = fs::read_to_string(entry_dir.join(ENTRY_FILE))?;
+    serde_json::from_str(&content).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+fn write_entry(entry_dir: &Path, entry: &TrashEntry) -> io::Result<()> {
+    let content = serde_json::to_vec(entry).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    storage::write_atomic(&entry_dir.join(ENTRY_FILE), &content)
+}
+
+// Renames when possible, falling back to copy and delete for lanes or
+// boards that sit on another filesystem than the trash
+fn move_path(from: &Path, to: &Path) -> io::Result<()> {
+    if fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+
+    copy_recursive(from, to)?;
+    if fs::symlink_metadata(from)?.is_dir() {
+        fs::remove_dir_all(from)
+    } else {
+        fs::remove_file(from)
+    }
+}
+
+fn copy_recursive(from: &Path, to: &Path) -> io::Result<()> {
+    let metadata = fs::symlink_metadata(from)?;
+
+    if metadata.file_type().is_symlink() {
+        copy_symlink(from, to)
+    } else if metadata.is_dir() {
+        fs::create_dir_all(to)?;
+        for entry in fs::read_dir(from)? {
+            let entry = entry?;
+            copy_recursive(&entry.path(), &to.join(entry.file_name()))?;
+        }
+        Ok(())
+    } else {
+        fs::copy(from, to).map(|_| ())
+    }
+}
+
+#[cfg(unix)]
+fn copy_symlink(from: &Path, to: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(fs::read_link(from)?, to)
+}
+
+#[cfg(not(unix))]
+fn copy_symlink(from: &Path, to: &Path) -> io::Result<()> {
+    fs::copy(from, to).map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    #[test]
+    fn trashes_and_restores_cards_and_lanes() {
+        let dir = TempDir::new("trash-restore");
+        let card = dir.write("board/lane/card.md", "card");
+        dir.write("board/other/kept.md", "kept");
+
+        let card_entry = trash(dir.path(), &card).unwrap();
+        let lane_entry = trash(dir.path(), &dir.path().join("board/other")).unwrap();
+        assert!(!card.exists());
+        assert!(!dir.path().join("board/other").exists());
+        assert_eq!((card_entry.original_path.as_str(), card_entry.is_dir), ("/board/lane/card.md", false));
+        assert_eq!((lane_entry.original_path.as_str(), lane_entry.is_dir), ("/board/other", true));
+        assert_eq!(list(dir.path()).unwrap().len(), 2);
+
+        restore(dir.path(), &card_entry.id).unwrap();
+        restore(dir.path(), &lane_entry.id).unwrap();
+        assert_eq!(fs::read_to_string(&card).unwrap(), "card");
+        assert_eq!(fs::read_to_string(dir.path().join("board/other/kept.md")).unwrap(), "kept");
+        assert!(list(dir.path()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn refuses_to_restore_over_an_existing_entry() {
+        let dir = TempDir::new("trash-conflict");
+        let card = dir.write("board/lane/card.md", "deleted");
+        let entry = trash(dir.path(), &card).unwrap();
+        dir.write("board/lane/card.md", "new");
+
+        let error = restore(dir.path(), &entry.id).unwrap_err();
+        assert!(matches!(error.code, ErrorCode::AlreadyExists));
+        assert_eq!(fs::read_to_string(&card).unwrap(), "new");
+        assert_eq!(list(dir.path()).unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,172 @@
+use crate::config::ConfigStore;
+use crate::error::{CommandError, ErrorCode};
+use crate::query::{self, SortBy, SortDirection};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::path::Path;
+
+// A named query and sort of a board, stored in `views.json` next to
+// `sort.json` and `tags.json` as `{ "<board>": [view, ...] }` so they follow
+// the config directory between machines
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SavedView {
+    pub name: String,
+    // Filter in the `query_cards` syntax, empty for every card
+    #[serde(default)]
+    pub query: String,
+    #[serde(default)]
+    pub sort: Option<SortBy>,
+    #[serde(default)]
+    pub direction: Option<SortDirection>,
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+impl SavedView {
+    fn validate(&mut self) -> Result<(), CommandError> {
+        self.name = self.name.trim().to_string();
+        if self.name.is_empty() {
+            return Err(CommandError::new(ErrorCode::InvalidName, "A view needs a name"));
+        }
+        query::parse(&self.query)?;
+        Ok(())
+    }
+}
+
+pub fn list(store: &ConfigStore, config_path: &Path, board: &str) -> Result<Vec<SavedView>, CommandError> {
+    let config = store.read(config_path).map_err(|e| CommandError::io(e, file_name(config_path)))?;
+    parse_views(config.get(board))
+}
+
+// Applies `change` to the views of `board` and returns the previous entry of
+// the board, for the undo journal. `views.json` is not written when `change`
+// fails.
+pub fn update<F>(store: &ConfigStore, config_path: &Path, board: &str, change: F) -> Result<Option<Value>, CommandError>
+where
+    F: FnOnce(&mut Vec<SavedView>) -> Result<(), CommandError>,
+{
+    store.try_update(config_path, |config| apply(config, board, change))
+        .map_err(|e| CommandError::io(e, file_name(config_path)))?
+}
+
+fn apply<F>(config: &mut Map<String, Value>, board: &str, change: F) -> Result<Option<Value>, CommandError>
+where
+    F: FnOnce(&mut Vec<SavedView>) -> Result<(), CommandError>,
+{
+    let previous = config.get(board).cloned();
+    let mut views = parse_views(previous.as_ref())?;
+    change(&mut views)?;
+
+    if views.is_empty() {
+        config.remove(board);
+    } else {
+        config.insert(board.to_string(), serde_json::to_value(&views).unwrap_or_default());
+    }
+    Ok(previous)
+}
+
+pub fn create(views: &mut Vec<SavedView>, mut view: SavedView) -> Result<(), CommandError> {
+    view.validate()?;
+    if find(views, &view.name).is_some() {
+        return Err(already_exists(&view.name));
+    }
+    views.push(view);
+    Ok(())
+}
+
+// Replaces the view called `name`, which may be renamed, keeping its position
+pub fn replace(views: &mut [SavedView], name: &str, mut view: SavedView) -> Result<(), CommandError> {
+    view.validate()?;
+    let index = find(views, name).ok_or_else(|| not_found(name))?;
+    if find(views, &view.name).is_some_and(|other| other != index) {
+        return Err(already_exists(&view.name));
+    }
+    views[index] = view;
+    Ok(())
+}
+
+pub fn delete(views: &mut Vec<SavedView>, name: &str) -> Result<(), CommandError> {
+    let index = find(views, name).ok_or_else(|| not_found(name))?;
+    views.remove(index);
+    Ok(())
+}
+
+// Names are matched case-insensitively, like lanes on most filesystems
+fn find(views: &[SavedView], name: &str) -> Option<usize> {
+    views.iter().position(|view| view.name.to_lowercase() == name.trim().to_lowercase())
+}
+
+fn parse_views(entry: Option<&Value>) -> Result<Vec<SavedView>, CommandError> {
+    match entry {
+        Some(entry) => serde_json::from_value(entry.clone())
+            .map_err(|e| CommandError::new(ErrorCode::InvalidData, format!("Saved views are invalid: {}", e))),
+        None => Ok(Vec::new()),
+    }
+}
+
+fn already_exists(name: &str) -> CommandError {
+    CommandError::new(ErrorCode::AlreadyExists, format!("A view named \"{}\" already exists", name))
+}
+
+fn not_found(name: &str) -> CommandError {
+    CommandError::new(ErrorCode::NotFound, format!("View \"{}\" does not exist", name))
+}
+
+fn file_name(config_path: &Path) -> &str {
+    config_path.file_name().and_then(|name| name.to_str()).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+    use std::fs;
+
+    fn view(name: &str, query: &str) -> SavedView {
+        SavedView { name: name.to_string(), query: query.to_string(), sort: None, direction: None, limit: None }
+    }
+
+    #[test]
+    fn updates_the_views_of_a_board() {
+        let dir = TempDir::new("views-update");
+        let path = dir.path().join("views.json");
+        let store = ConfigStore::default();
+
+        let previous = update(&store, &path, "/board", |views| create(views, view(" Urgent ", "tag:urgent"))).unwrap();
+        assert_eq!(previous, None);
+        update(&store, &path, "/other", |views| create(views, view("Mine", "assignee:me"))).unwrap();
+        assert_eq!(list(&store, &path, "/board").unwrap(), vec![view("Urgent", "tag:urgent")]);
+
+        let previous = update(&store, &path, "/board", |views| replace(views, "urgent", view("Late", "due:overdue"))).unwrap();
+        assert_eq!(previous, Some(serde_json::to_value(vec![view("Urgent", "tag:urgent")]).unwrap()));
+        assert_eq!(list(&store, &path, "/board").unwrap(), vec![view("Late", "due:overdue")]);
+
+        update(&store, &path, "/board", |views| delete(views, "LATE")).unwrap();
+        assert!(list(&store, &path, "/board").unwrap().is_empty());
+        assert!(!store.read(&path).unwrap().contains_key("/board"));
+        assert_eq!(list(&store, &path, "/other").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn leaves_the_file_alone_when_the_change_fails() {
+        let dir = TempDir::new("views-failed-update");
+        let path = dir.path().join("views.json");
+        let store = ConfigStore::default();
+        update(&store, &path, "/board", |views| create(views, view("Urgent", "tag:urgent"))).unwrap();
+        let written = fs::read_to_string(&path).unwrap();
+        let modified = fs::metadata(&path).unwrap().modified().unwrap();
+
+        let failures = [
+            update(&store, &path, "/board", |views| create(views, view("urgent", ""))),
+            update(&store, &path, "/board", |views| create(views, view("  ", ""))),
+            update(&store, &path, "/board", |views| create(views, view("Broken", "tag:"))),
+            update(&store, &path, "/board", |views| delete(views, "Missing")),
+        ];
+        for failure in failures {
+            assert!(failure.is_err());
+        }
+        assert_eq!(fs::read_to_string(&path).unwrap(), written);
+        assert_eq!(fs::metadata(&path).unwrap().modified().unwrap(), modified);
+    }
+}
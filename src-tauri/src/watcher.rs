@@ -0,0 +1,623 @@
+use notify::event::{MetadataKind, ModifyKind, RenameMode};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use crate::search::StaleMarker;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter, EventTarget};
+
+// Quiet period used to collapse bursts of events (e.g. an editor's save dance)
+const DEBOUNCE: Duration = Duration::from_millis(250);
+// Upper bound on how long a continuous stream of events can delay a notification
+const MAX_DELAY: Duration = Duration::from_secs(1);
+// How long events caused by the app's own writes are ignored
+const OWN_WRITE_TTL: Duration = Duration::from_secs(1);
+
+// Paths recently written by the app itself, so the watcher does not echo
+// them back to the frontend that just made the change
+#[derive(Default, Clone)]
+pub struct OwnWrites(Arc<Mutex<HashMap<PathBuf, Instant>>>);
+
+impl OwnWrites {
+    pub fn record(&self, path: &Path) {
+        let mut writes = self.0.lock().unwrap();
+        let now = Instant::now();
+        writes.retain(|_, at| now.duration_since(*at) < OWN_WRITE_TTL);
+        writes.insert(path.to_path_buf(), now);
+    }
+
+    // Entries are prefixes, so removing a lane also covers every card inside it
+    fn contains(&self, path: &Path) -> bool {
+        let writes = self.0.lock().unwrap();
+        let now = Instant::now();
+        writes.iter().any(|(written, at)| {
+            now.duration_since(*at) < OWN_WRITE_TTL && path.starts_with(written)
+        })
+    }
+}
+
+// Labels of the windows subscribed to a board watcher, shared with its
+// debounce thread so changes only reach them. Every window hears about
+// config changes from its own board's watcher, and so only once.
+#[derive(Default, Clone)]
+pub struct Windows(Arc<Mutex<HashSet<String>>>);
+
+impl Windows {
+    fn insert(&self, window: &str) {
+        self.0.lock().unwrap().insert(window.to_string());
+    }
+
+    fn remove(&self, window: &str) {
+        self.0.lock().unwrap().remove(window);
+    }
+
+    fn contains(&self, window: &str) -> bool {
+        self.0.lock().unwrap().contains(window)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.0.lock().unwrap().is_empty()
+    }
+
+    fn labels(&self) -> Vec<String> {
+        self.0.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum ChangeKind {
+    CardCreated,
+    CardModified,
+    CardDeleted,
+    CardRenamed,
+    LaneCreated,
+    LaneRenamed,
+    LaneDeleted,
+    TagsChanged,
+    SortChanged,
+    ViewsChanged,
+    DoneLanesChanged,
+    // Events were lost, the whole board has to be reloaded
+    Rescan,
+}
+
+// Config files shared by every board, with the change they are reported as
+const CONFIG_FILES: [(&str, ChangeKind); 4] = [
+    ("tags.json", ChangeKind::TagsChanged),
+    ("sort.json", ChangeKind::SortChanged),
+    ("views.json", ChangeKind::ViewsChanged),
+    ("done-lanes.json", ChangeKind::DoneLanesChanged),
+];
+
+fn is_config_change(kind: ChangeKind) -> bool {
+    CONFIG_FILES.iter().any(|(_, config_kind)| *config_kind == kind)
+}
+
+// Payload of the `files-changed` event. Paths are relative to the tasks
+// directory and use the same "/board/lane/card.md" form as the commands;
+// for config changes `path` is the board whose entry changed.
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Change {
+    kind: ChangeKind,
+    board: String,
+    path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    old_path: Option<String>,
+}
+
+impl Change {
+    fn rescan() -> Self {
+        Change { kind: ChangeKind::Rescan, board: String::new(), path: String::new(), old_path: None }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WatcherStatus {
+    watching: bool,
+    board: Option<String>,
+    // Every board currently watched, across all windows
+    boards: Vec<String>,
+}
+
+struct BoardWatcher {
+    board: String,
+    // Never reused, so a watcher restarted on the same root is told apart
+    id: u64,
+    windows: Windows,
+    _watcher: RecommendedWatcher,
+}
+
+// Active watchers keyed by the board directory they watch. Windows that have
+// the same board open share one watcher, which is dropped, and with it its
+// debounce thread, once the last of them unsubscribes.
+#[derive(Default)]
+pub struct Watchers {
+    watchers: HashMap<PathBuf, BoardWatcher>,
+    next_id: u64,
+}
+
+impl Watchers {
+    // Points `window` at `root`, reusing an existing watcher for that root.
+    // Subscribing again to the same board (e.g. after a webview reload) is a no-op.
+    // `start` is handed the windows the new watcher reports to.
+    pub fn subscribe<F>(&mut self, window: &str, board: &str, root: &Path, start: F) -> notify::Result<()>
+    where
+        F: FnOnce(Windows) -> notify::Result<RecommendedWatcher>,
+    {
+        if self.watchers.get(root).is_some_and(|watcher| watcher.windows.contains(window)) {
+            return Ok(());
+        }
+
+        self.unsubscribe(window);
+
+        if !self.watchers.contains_key(root) {
+            let windows = Windows::default();
+            let watcher = start(windows.clone())?;
+            self.next_id += 1;
+            self.watchers.insert(root.to_path_buf(), BoardWatcher {
+                board: board.to_string(),
+                id: self.next_id,
+                windows,
+                _watcher: watcher,
+            });
+        }
+
+        if let Some(watcher) = self.watchers.get(root) {
+            watcher.windows.insert(window);
+        }
+        Ok(())
+    }
+
+    pub fn unsubscribe(&mut self, window: &str) {
+        for watcher in self.watchers.values() {
+            watcher.windows.remove(window);
+        }
+        self.watchers.retain(|_, watcher| !watcher.windows.is_empty());
+    }
+
+    // Root and id of every running watcher, for the search index to tell
+    // which boards have had all their changes reported
+    pub fn roots(&self) -> Vec<(PathBuf, u64)> {
+        self.watchers.iter().map(|(root, watcher)| (root.clone(), watcher.id)).collect()
+    }
+
+    pub fn status(&self, window: &str) -> WatcherStatus {
+        let board = self.watchers.values()
+            .find(|watcher| watcher.windows.contains(window))
+            .map(|watcher| watcher.board.clone());
+
+        let mut boards: Vec<String> = self.watchers.values().map(|watcher| watcher.board.clone()).collect();
+        boards.sort();
+
+        WatcherStatus { watching: board.is_some(), board, boards }
+    }
+}
+
+// Recursively watches a board directory, plus the config files listed in
+// `CONFIG_FILES`, and emits `files-changed` to `windows` once per burst of
+// external changes. Paths in the emitted changes stay relative to `tasks_root`. Raw
+// events are also handed to the search index. The watcher stops when the
+// returned handle is dropped.
+pub fn watch(app_handle: AppHandle, windows: Windows, tasks_root: &Path, board_root: &Path, config_root: &Path, own_writes: OwnWrites, search: StaleMarker) -> notify::Result<RecommendedWatcher> {
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+        search.mark(&event);
+        let _ = tx.send(event);
+    })?;
+    watcher.watch(board_root, RecursiveMode::Recursive)?;
+    watcher.watch(config_root, RecursiveMode::NonRecursive)?;
+
+    let mut classifier = Classifier {
+        tasks_root: tasks_root.to_path_buf(),
+        config_root: config_root.to_path_buf(),
+        config: HashMap::new(),
+        own_writes,
+    };
+    for (name, _) in CONFIG_FILES {
+        let path = config_root.join(name);
+        classifier.config.insert(path.clone(), read_config(&path));
+    }
+
+    std::thread::spawn(move || debounce(app_handle, windows, rx, classifier));
+
+    Ok(watcher)
+}
+
+fn debounce(app_handle: AppHandle, windows: Windows, rx: Receiver<notify::Result<Event>>, mut classifier: Classifier) {
+    // `recv` fails once the watcher, and with it the sender, has been dropped
+    while let Ok(first) = rx.recv() {
+        let started = Instant::now();
+        let mut batch = Batch::default();
+        classifier.classify(&first, &mut batch);
+
+        while started.elapsed() < MAX_DELAY {
+            match rx.recv_timeout(DEBOUNCE) {
+                Ok(event) => classifier.classify(&event, &mut batch),
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+        }
+
+        if !batch.changes.is_empty() {
+            for label in windows.labels() {
+                let _ = app_handle.emit_to(EventTarget::webview_window(label), "files-changed", &batch.changes);
+            }
+        }
+    }
+}
+
+struct Classifier {
+    tasks_root: PathBuf,
+    config_root: PathBuf,
+    // Last known content of each config file, used to tell which boards changed
+    config: HashMap<PathBuf, Map<String, Value>>,
+    own_writes: OwnWrites,
+}
+
+impl Classifier {
+    fn classify(&mut self, event: &notify::Result<Event>, batch: &mut Batch) {
+        let event = match event {
+            Ok(event) => event,
+            // Errors usually mean events were dropped
+            Err(_) => return batch.push(Change::rescan()),
+        };
+
+        if event.need_rescan() {
+            return batch.push(Change::rescan());
+        }
+
+        match event.kind {
+            EventKind::Access(_) => return,
+            EventKind::Modify(ModifyKind::Metadata(MetadataKind::AccessTime)) => return,
+            _ => {}
+        }
+
+        for path in &event.paths {
+            if path.parent() == Some(self.config_root.as_path()) && self.config.contains_key(path) {
+                self.config_changed(path, batch);
+            }
+        }
+
+        if let EventKind::Modify(ModifyKind::Name(RenameMode::Both)) = event.kind {
+            if let [from, to] = event.paths.as_slice() {
+                return self.renamed(from, to, batch);
+            }
+        }
+
+        for path in &event.paths {
+            let op = match event.kind {
+                EventKind::Create(_) | EventKind::Modify(ModifyKind::Name(RenameMode::To)) => Op::Created,
+                EventKind::Remove(_) | EventKind::Modify(ModifyKind::Name(RenameMode::From)) => Op::Deleted,
+                EventKind::Modify(ModifyKind::Name(_)) if path.exists() => Op::Created,
+                EventKind::Modify(ModifyKind::Name(_)) => Op::Deleted,
+                _ if path.exists() => Op::Modified,
+                _ => Op::Deleted,
+            };
+            if let Some(change) = self.entry_change(path, op) {
+                batch.push(change);
+                if matches!(event.kind, EventKind::Create(_)) && path.is_dir() {
+                    self.created_subtree(path, batch);
+                }
+            }
+        }
+    }
+
+    // Entries created together with their directory (e.g. `mkdir -p` or a
+    // copied lane) land before the directory is watched, so they are listed here
+    fn created_subtree(&self, dir: &Path, batch: &mut Batch) {
+        for entry in fs::read_dir(dir).into_iter().flatten().flatten() {
+            let path = entry.path();
+            if let Some(change) = self.entry_change(&path, Op::Created) {
+                batch.push(change);
+                if path.is_dir() {
+                    self.created_subtree(&path, batch);
+                }
+            }
+        }
+    }
+
+    fn renamed(&self, from: &Path, to: &Path, batch: &mut Batch) {
+        // Moving a file out of or into a hidden name (e.g. an editor's temp
+        // file) is a deletion or creation as far as the board is concerned
+        let old = self.entry_change(from, Op::Deleted);
+        let new = self.entry_change(to, Op::Created);
+
+        match (old, new) {
+            (Some(old), Some(new)) => {
+                let kind = if new.kind == ChangeKind::CardCreated { ChangeKind::CardRenamed } else { ChangeKind::LaneRenamed };
+                batch.push(Change { kind, board: new.board, path: new.path, old_path: Some(old.path) });
+            }
+            (Some(change), None) | (None, Some(change)) => batch.push(change),
+            (None, None) => {}
+        }
+    }
+
+    // Maps a path under the tasks directory to a card or lane change, skipping
+    // hidden entries, editor leftovers such as `card.md~` and the app's own writes
+    fn entry_change(&self, path: &Path, op: Op) -> Option<Change> {
+        if self.own_writes.contains(path) {
+            return None;
+        }
+
+        let relative = path.strip_prefix(&self.tasks_root).ok()?;
+        let mut parts = Vec::new();
+        for component in relative.components() {
+            if let Component::Normal(part) = component {
+                let part = part.to_string_lossy();
+                if part.starts_with('.') || part.ends_with('~') {
+                    return None;
+                }
+                parts.push(part.to_string());
+            }
+        }
+
+        let is_card = path.extension().is_some_and(|ext| ext == "md") && !path.is_dir();
+        // Deleted paths can no longer be inspected and may have been a lane
+        let is_lane = !is_card && (path.is_dir() || !path.exists());
+
+        let kind = match (is_card, is_lane, op) {
+            (true, _, Op::Created) => ChangeKind::CardCreated,
+            (true, _, Op::Modified) => ChangeKind::CardModified,
+            (true, _, Op::Deleted) => ChangeKind::CardDeleted,
+            (_, true, Op::Created) => ChangeKind::LaneCreated,
+            (_, true, Op::Deleted) => ChangeKind::LaneDeleted,
+            // Directory mtimes change along with their content, which is reported on its own
+            _ => return None,
+        };
+
+        let board_depth = if is_card { parts.len().saturating_sub(2) } else { parts.len().saturating_sub(1) };
+
+        Some(Change {
+            kind,
+            board: to_board_path(&parts[..board_depth]),
+            path: to_board_path(&parts),
+            old_path: None,
+        })
+    }
+
+    fn config_changed(&mut self, path: &Path, batch: &mut Batch) {
+        let kind = CONFIG_FILES.iter()
+            .find(|(name, _)| path.ends_with(name))
+            .map_or(ChangeKind::SortChanged, |(_, kind)| *kind);
+        let current = read_config(path);
+        let previous = self.config.insert(path.to_path_buf(), current.clone()).unwrap_or_default();
+
+        // The snapshot is kept up to date even for the app's own writes, so
+        // the next external change is compared against what is on disk
+        if self.own_writes.contains(path) {
+            return;
+        }
+
+        let mut boards: Vec<&String> = current.keys().chain(previous.keys()).collect();
+        boards.sort();
+        boards.dedup();
+
+        for board in boards {
+            if current.get(board) != previous.get(board) {
+                batch.push(Change { kind, board: board.clone(), path: board.clone(), old_path: None });
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Op {
+    Created,
+    Modified,
+    Deleted,
+}
+
+// Changes collected during one debounce window, coalesced per path so an
+// editor replacing a file shows up as a single modification
+#[derive(Default)]
+struct Batch {
+    changes: Vec<Change>,
+}
+
+impl Batch {
+    fn push(&mut self, change: Change) {
+        use ChangeKind::*;
+
+        if change.kind == Rescan {
+            if !self.changes.iter().any(|pending| pending.kind == Rescan) {
+                self.changes.push(change);
+            }
+            return;
+        }
+
+        // A rename replaces the deletion and creation inotify reports first
+        if let Some(old_path) = &change.old_path {
+            self.changes.retain(|pending| {
+                let removed_old = pending.path == *old_path && matches!(pending.kind, CardDeleted | LaneDeleted);
+                let created_new = pending.path == change.path && matches!(pending.kind, CardCreated | LaneCreated);
+                !removed_old && !created_new
+            });
+            self.changes.push(change);
+            return;
+        }
+
+        // Config changes are reported once per board
+        if is_config_change(change.kind) {
+            if !self.changes.iter().any(|pending| pending.kind == change.kind && pending.board == change.board) {
+                self.changes.push(change);
+            }
+            return;
+        }
+
+        let index = self.changes.iter().rposition(|pending| {
+            pending.path == change.path && pending.old_path.is_none() && !is_config_change(pending.kind)
+        });
+        let index = match index {
+            Some(index) => index,
+            None => return self.changes.push(change),
+        };
+
+        match (self.changes[index].kind, change.kind) {
+            (pending, incoming) if pending == incoming => {}
+            (CardCreated, CardModified) => {}
+            (CardCreated, CardDeleted) | (LaneCreated, LaneDeleted) => {
+                self.changes.remove(index);
+            }
+            (CardDeleted, CardCreated) | (CardModified, CardDeleted) => {
+                let kind = if change.kind == CardCreated { CardModified } else { CardDeleted };
+                self.changes[index].kind = kind;
+            }
+            _ => self.changes.push(change),
+        }
+    }
+}
+
+fn to_board_path(parts: &[String]) -> String {
+    parts.iter().map(|part| format!("/{}", part)).collect()
+}
+
+fn read_config(path: &Path) -> Map<String, Value> {
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|content| serde_json::from_str::<Map<String, Value>>(&content).ok())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ChangeKind::*;
+
+    fn change(kind: ChangeKind, path: &str) -> Change {
+        let board = path.rsplit_once('/').map(|(board, _)| board.to_string()).unwrap_or_default();
+        Change { kind, board, path: path.to_string(), old_path: None }
+    }
+
+    fn rename(kind: ChangeKind, from: &str, to: &str) -> Change {
+        Change { old_path: Some(from.to_string()), ..change(kind, to) }
+    }
+
+    fn config(kind: ChangeKind, board: &str) -> Change {
+        Change { kind, board: board.to_string(), path: board.to_string(), old_path: None }
+    }
+
+    fn coalesce(changes: Vec<Change>) -> Vec<(ChangeKind, String, Option<String>)> {
+        let mut batch = Batch::default();
+        for change in changes {
+            batch.push(change);
+        }
+        batch.changes.into_iter().map(|change| (change.kind, change.path, change.old_path)).collect()
+    }
+
+    fn expected(kind: ChangeKind, path: &str) -> (ChangeKind, String, Option<String>) {
+        (kind, path.to_string(), None)
+    }
+
+    #[test]
+    fn renames_absorb_the_deletion_and_creation_before_them() {
+        let changes = coalesce(vec![
+            change(CardDeleted, "/lane/old.md"),
+            change(CardCreated, "/lane/new.md"),
+            change(CardModified, "/lane/other.md"),
+            rename(CardRenamed, "/lane/old.md", "/lane/new.md"),
+        ]);
+        assert_eq!(changes, vec![
+            expected(CardModified, "/lane/other.md"),
+            (CardRenamed, "/lane/new.md".to_string(), Some("/lane/old.md".to_string())),
+        ]);
+
+        let changes = coalesce(vec![
+            change(LaneDeleted, "/old"),
+            change(LaneCreated, "/new"),
+            rename(LaneRenamed, "/old", "/new"),
+        ]);
+        assert_eq!(changes, vec![(LaneRenamed, "/new".to_string(), Some("/old".to_string()))]);
+    }
+
+    #[test]
+    fn created_then_deleted_entries_cancel_out() {
+        assert!(coalesce(vec![change(CardCreated, "/lane/card.md"), change(CardDeleted, "/lane/card.md")]).is_empty());
+        assert!(coalesce(vec![change(LaneCreated, "/lane"), change(LaneDeleted, "/lane")]).is_empty());
+        assert!(coalesce(vec![
+            change(CardCreated, "/lane/card.md"),
+            change(CardModified, "/lane/card.md"),
+            change(CardDeleted, "/lane/card.md"),
+        ]).is_empty());
+    }
+
+    #[test]
+    fn replaced_files_are_modified() {
+        assert_eq!(
+            coalesce(vec![change(CardDeleted, "/lane/card.md"), change(CardCreated, "/lane/card.md")]),
+            vec![expected(CardModified, "/lane/card.md")],
+        );
+        assert_eq!(
+            coalesce(vec![change(CardModified, "/lane/card.md"), change(CardDeleted, "/lane/card.md")]),
+            vec![expected(CardDeleted, "/lane/card.md")],
+        );
+        assert_eq!(
+            coalesce(vec![change(CardCreated, "/lane/card.md"), change(CardModified, "/lane/card.md")]),
+            vec![expected(CardCreated, "/lane/card.md")],
+        );
+        assert_eq!(
+            coalesce(vec![change(CardModified, "/lane/card.md"), change(CardModified, "/lane/card.md")]),
+            vec![expected(CardModified, "/lane/card.md")],
+        );
+    }
+
+    #[test]
+    fn reports_config_changes_once_per_board_and_file() {
+        let changes = coalesce(vec![
+            config(TagsChanged, "/board"),
+            config(TagsChanged, "/board"),
+            config(TagsChanged, "/other"),
+            config(SortChanged, "/board"),
+            // A lane named like the board is not a config change
+            change(LaneDeleted, "/board"),
+            config(TagsChanged, "/board"),
+        ]);
+        assert_eq!(changes, vec![
+            expected(TagsChanged, "/board"),
+            expected(TagsChanged, "/other"),
+            expected(SortChanged, "/board"),
+            expected(LaneDeleted, "/board"),
+        ]);
+    }
+
+    #[test]
+    fn reports_a_single_rescan() {
+        let changes = coalesce(vec![change(CardModified, "/lane/card.md"), Change::rescan(), Change::rescan()]);
+        assert_eq!(changes, vec![expected(CardModified, "/lane/card.md"), (Rescan, String::new(), None)]);
+    }
+
+    #[test]
+    fn shares_one_watcher_and_its_windows_per_board() {
+        let mut watchers = Watchers::default();
+        let started = std::cell::RefCell::new(Vec::new());
+        let start = |windows: Windows| {
+            started.borrow_mut().push(windows);
+            notify::recommended_watcher(|_: notify::Result<Event>| {})
+        };
+
+        watchers.subscribe("main", "/board", Path::new("/tasks/board"), start).unwrap();
+        watchers.subscribe("second", "/board", Path::new("/tasks/board"), start).unwrap();
+        watchers.subscribe("third", "/other", Path::new("/tasks/other"), start).unwrap();
+        assert_eq!(started.borrow().len(), 2);
+        let mut labels = started.borrow()[0].labels();
+        labels.sort();
+        assert_eq!(labels, vec!["main", "second"]);
+        assert_eq!(started.borrow()[1].labels(), vec!["third"]);
+
+        // Switching boards moves the window to the other watcher
+        watchers.subscribe("second", "/other", Path::new("/tasks/other"), start).unwrap();
+        assert_eq!(started.borrow().len(), 2);
+        assert_eq!(started.borrow()[0].labels(), vec!["main"]);
+        assert_eq!(watchers.status("second").board.as_deref(), Some("/other"));
+
+        watchers.unsubscribe("main");
+        assert_eq!(watchers.roots().len(), 1);
+    }
+}
@@ -118,7 +118,7 @@ function App() {
     let newCards = resources.map((resource) => resource.files.map((file) => ({ ...file, lane: resource.name }))).flat()
 
     const currentTags = newCards
-      .map((card) => getTagsByCardContent(card.content))
+      .map((card) => card.tags)
       .reduce((prev, curr) => [...prev, ...curr], [])
     const currentTagsWithoutDuplicates = currentTags.filter(
       (tag, index, arr) =>
@@ -140,10 +140,8 @@ function App() {
     newCards = newCards
       .map((card) => {
         const newCard = structuredClone(card)
-        const cardTagsNames = getTagsByCardContent(card.content) || []
-        newCard.tags = tagsWithColors.filter((tagOption) => cardTagsNames.includes(tagOption.name))
-        const dueDateStringMatch = newCard.content.match(/\[due:(.*?)\]/)
-        newCard.dueDate = dueDateStringMatch?.length ? dueDateStringMatch[1] : ''
+        newCard.tags = tagsWithColors.filter((tagOption) => card.tags.includes(tagOption.name))
+        newCard.dueDate = card.due || ''
         return newCard
       })
       .toSorted((a, b) => {
@@ -177,21 +175,27 @@ function App() {
     )
     const newCard = newCards[newCardIndex]
     newCard.content = newContent
-    await api.updateResource(`${board()}/${newCard.lane}/${newCard.name}.md`, null, newContent)
+    const cardPath = `${board()}/${newCard.lane}/${newCard.name}.md`
+    let updatedCard
+    try {
+      updatedCard = (await api.updateResource(cardPath, null, newContent, newCard.version)).card
+    } catch (error) {
+      if (error?.code !== 'conflict') {
+        throw error
+      }
+      if (!window.confirm(`"${newCard.name}" was changed outside the app. Overwrite it with your changes?`)) {
+        return fetchData()
+      }
+      updatedCard = (await api.updateResource(cardPath, null, newContent)).card
+    }
+    newCard.version = updatedCard.version
     const remoteTagOptions = await api.getTags(board()).then((resJson) => {
       return Object.entries(resJson).map((entry) => ({
         name: entry[0],
         backgroundColor: entry[1]
       }))
     })
-    const cardTags = getTagsByCardContent(newContent)
-    const cardTagsWithoutDuplicates = cardTags.filter(
-      (tag, index, arr) =>
-        arr.findIndex((duplicatedTag) => {
-          return duplicatedTag.toLowerCase() === tag.toLowerCase()
-        }) === index
-    )
-    const cardTagOptions = cardTagsWithoutDuplicates.map((tagName) => {
+    const cardTagOptions = updatedCard.tags.map((tagName) => {
       const remoteTagOption = remoteTagOptions.find((option) => option.name === tagName)
       const tagColor =
         remoteTagOption?.backgroundColor || getTagBackgroundCssColor(pickTagColorIndexBasedOnHash(tagName))
@@ -202,8 +206,8 @@ function App() {
     })
     newCard.tags = cardTagOptions
     newCard.lastUpdated = new Date().toISOString()
-    const dueDateStringMatch = newCard.content.match(/\[due:(.*?)\]/)
-    newCard.dueDate = dueDateStringMatch?.length ? dueDateStringMatch[1] : ''
+    newCard.dueDate = updatedCard.due || ''
+    newCard.tokens = updatedCard.tokens
     newCards[newCardIndex] = newCard
     setCards(newCards)
     const localTagOptions = cardTagOptions.filter(
@@ -214,9 +218,61 @@ function App() {
     navigate(`${basePath()}${board()}/${newCard.name}.md`)
   }
 
-  function getTagsByCardContent(text) {
-    const tags = [...text.matchAll(/\[tag:(.*?)\]/g)].map((tagMatch) => tagMatch[1].trim()).filter((tag) => tag !== '')
-    return tags
+  function getCardTagOptions(tagNames) {
+    return tagNames.map((tagName) => {
+      const tagOption = tagsOptions().find((option) => option.name === tagName)
+      return (
+        tagOption || {
+          name: tagName,
+          backgroundColor: getTagBackgroundCssColor(pickTagColorIndexBasedOnHash(tagName))
+        }
+      )
+    })
+  }
+
+  async function patchCard(change) {
+    const [lane, fileName] = change.path.substring(board().length + 1).split('/')
+    const name = fileName.replace(/\.md$/, '')
+    const isChangedCard = (card) => card.lane === lane && card.name === name
+    if (change.kind === 'cardDeleted') {
+      setCards(cards().filter((card) => !isChangedCard(card)))
+      return
+    }
+    const card = await api.getCard(change.path)
+    if (!card) {
+      return
+    }
+    const newCard = { ...card, lane }
+    newCard.tags = getCardTagOptions(card.tags)
+    newCard.dueDate = card.due || ''
+    const newTagOptions = newCard.tags.filter((tag) => !tagsOptions().some((option) => option.name === tag.name))
+    const cardIndex = cards().findIndex(isChangedCard)
+    const newCards = structuredClone(cards())
+    if (cardIndex === -1) {
+      newCards.push(newCard)
+    } else {
+      newCards[cardIndex] = newCard
+    }
+    batch(() => {
+      setTagsOptions([...tagsOptions(), ...newTagOptions])
+      setCards(newCards)
+      if (selectedCard() && isChangedCard(selectedCard())) {
+        setRenderUID(v7())
+      }
+    })
+  }
+
+  // Patches only the affected cards when possible, falling back to a full reload
+  // for lane, config and rename changes
+  async function handleFilesChanged(changes) {
+    const boardChanges = changes.filter((change) => change.board === board() || change.kind === 'rescan')
+    const patchableKinds = ['cardCreated', 'cardModified', 'cardDeleted']
+    if (!boardChanges.every((change) => patchableKinds.includes(change.kind))) {
+      return fetchData()
+    }
+    for (const change of boardChanges) {
+      await patchCard(change)
+    }
   }
 
   function handleSortSelectOnChange(e) {
@@ -268,20 +324,36 @@ function App() {
     setLaneBeingRenamedName(newName)
   }
 
-  function renameLane() {
-    api.updateResource(`${board()}/${laneBeingRenamedName()}`, `${board()}/${newLaneName()}`)
-    const newLanes = structuredClone(lanes())
-    const newLaneIndex = newLanes.findIndex((laneToFind) => laneToFind === laneBeingRenamedName())
-    const newLane = newLanes[newLaneIndex]
-    const newCards = structuredClone(cards()).map((card) => ({
-      ...card,
-      lane: card.lane === newLane ? newLaneName() : card.lane
-    }))
-    setCards(newCards)
-    newLanes[newLaneIndex] = newLaneName()
-    setLanes(newLanes)
+  // Name of the card or lane at a path returned by the backend, which differs
+  // from the requested one when characters had to be replaced
+  function nameFromPath(path) {
+    return path.split('/').pop().replace(/\.md$/, '')
+  }
+
+  function replaceLaneName(oldName, newName) {
+    if (oldName === newName) {
+      return
+    }
+    setLanes(lanes().map((lane) => (lane === oldName ? newName : lane)))
+    setCards(cards().map((card) => (card.lane === oldName ? { ...card, lane: newName } : card)))
+  }
+
+  async function renameLane() {
+    const oldName = laneBeingRenamedName()
+    const newName = newLaneName()
+    const previousLanes = lanes()
+    const previousCards = cards()
+    replaceLaneName(oldName, newName)
     setNewLaneName(null)
     setLaneBeingRenamedName(null)
+    try {
+      const updated = await api.updateResource(`${board()}/${oldName}`, `${board()}/${newName}`)
+      replaceLaneName(newName, nameFromPath(updated.path))
+    } catch (error) {
+      setLanes(previousLanes)
+      setCards(previousCards)
+      throw error
+    }
   }
 
   function deleteLane(lane) {
@@ -332,9 +404,9 @@ function App() {
     })
   }
 
-  function handleOnSelectedCardNameChange(newName) {
-    renameCard(selectedCard().name, newName)
-    navigate(`${basePath()}${board()}/${newName}.md`)
+  async function handleOnSelectedCardNameChange(newName) {
+    const renamedName = await renameCard(selectedCard().name, newName)
+    navigate(`${basePath()}${board()}/${renamedName}.md`)
   }
 
   function handleDeleteCardsByLane(lane) {
@@ -346,19 +418,31 @@ function App() {
     setCards(cardsToKeep)
   }
 
-  function renameCard(oldName, newName) {
-    const newCards = structuredClone(cards())
-    const newCardIndex = newCards.findIndex((card) => card.name === oldName)
-    const newCard = newCards[newCardIndex]
+  // Resolves to the name the card ended up with
+  async function renameCard(oldName, newName) {
+    const previousCards = cards()
+    const card = previousCards.find((card) => card.name === oldName)
     const newCardNameWithoutSpaces = newName.trim()
-    api.updateResource(
-      `${board()}/${newCard.lane}/${newCard.name}.md`,
-      `${board()}/${newCard.lane}/${newCardNameWithoutSpaces}.md`
-    )
-    newCard.name = newCardNameWithoutSpaces
-    newCards[newCardIndex] = newCard
-    setCards(newCards)
+    const replaceName = (from, to) =>
+      setCards(
+        cards().map((other) => (other.lane === card.lane && other.name === from ? { ...other, name: to } : other))
+      )
+    replaceName(oldName, newCardNameWithoutSpaces)
     setCardBeingRenamed(null)
+    try {
+      const updated = await api.updateResource(
+        `${board()}/${card.lane}/${oldName}.md`,
+        `${board()}/${card.lane}/${newCardNameWithoutSpaces}.md`
+      )
+      const renamedName = nameFromPath(updated.path)
+      if (renamedName !== newCardNameWithoutSpaces) {
+        replaceName(newCardNameWithoutSpaces, renamedName)
+      }
+      return renamedName
+    } catch (error) {
+      setCards(previousCards)
+      throw error
+    }
   }
 
   async function updateTagColorFromExpandedCard(tagColor) {
@@ -456,15 +540,55 @@ function App() {
     setCardBeingRenamed(card)
   }
 
+  // Ctrl+Z / Ctrl+Shift+Z (or Ctrl+Y) revert changes on disk, except while
+  // typing, where the input or editor keeps its own history
+  async function handleUndoRedoKeyDown(e) {
+    const key = e.key.toLowerCase()
+    if (!(e.ctrlKey || e.metaKey) || (key !== 'z' && key !== 'y')) {
+      return
+    }
+    if (e.target.closest?.('input, textarea, select, [contenteditable="true"]')) {
+      return
+    }
+    e.preventDefault()
+    const changed = key === 'y' || e.shiftKey ? await api.redo() : await api.undo()
+    if (changed) {
+      fetchData()
+    }
+  }
+
+  // Commands reject with `{ code, path, message }`, where message is written
+  // for the user. Conflicts are handled where the card is saved.
+  function handleCommandError(event) {
+    const error = event.reason
+    if (!error?.code || !error.message || error.code === 'conflict') {
+      return
+    }
+    event.preventDefault()
+    window.alert(error.message)
+    fetchData()
+  }
+
   onMount(() => {
     const url = window.location.href
     if (!url.match(/\/$/)) {
       window.location.replace(`${url}/`)
     }
     fetchData()
-    window.addEventListener('filesChanged', () => {
-      fetchData()
+    window.addEventListener('filesChanged', (event) => {
+      handleFilesChanged(event.detail || [])
     })
+    window.addEventListener('keydown', handleUndoRedoKeyDown)
+    window.addEventListener('unhandledrejection', handleCommandError)
+  })
+
+  onCleanup(() => {
+    window.removeEventListener('keydown', handleUndoRedoKeyDown)
+    window.removeEventListener('unhandledrejection', handleCommandError)
+  })
+
+  createEffect(() => {
+    api.watchBoard(board())
   })
 
   createEffect(() => {
@@ -501,21 +625,37 @@ function App() {
     setLanes([...newLanes.slice(0, changedLane.index), lane, ...newLanes.slice(changedLane.index)])
   }
 
-  function handleCardsSortChange(changedCard) {
+  async function handleCardsSortChange(changedCard) {
     const cardName = changedCard.id.slice('card-'.length)
-    const oldIndex = cards().findIndex((card) => card.name === cardName)
-    const card = cards()[oldIndex]
+    const previousCards = cards()
+    const oldCard = previousCards.find((card) => card.name === cardName)
     const newCardLane = changedCard.to.slice('lane-content-'.length)
-    api.updateResource(`${board()}/${card.lane}/${cardName}.md`, `${board()}/${newCardLane}/${cardName}.md`)
-    card.lane = newCardLane
+    const card = { ...oldCard, lane: newCardLane }
     const newCards = lanes().flatMap((lane) => {
-      let laneCards = cards().filter((card) => card.lane === lane && card.name !== cardName)
+      let laneCards = previousCards.filter((card) => card.lane === lane && card.name !== cardName)
       if (lane === newCardLane) {
         laneCards = [...laneCards.slice(0, changedCard.index), card, ...laneCards.slice(changedCard.index)]
       }
       return laneCards
     })
     setCards(newCards)
+    try {
+      const updated = await api.updateResource(
+        `${board()}/${oldCard.lane}/${cardName}.md`,
+        `${board()}/${newCardLane}/${cardName}.md`
+      )
+      const movedName = nameFromPath(updated.path)
+      if (movedName !== cardName) {
+        setCards(
+          cards().map((other) =>
+            other.lane === newCardLane && other.name === cardName ? { ...other, name: movedName } : other
+          )
+        )
+      }
+    } catch (error) {
+      setCards(previousCards)
+      throw error
+    }
   }
 
   const disableCardsDrag = createMemo(() => sort() !== 'none')
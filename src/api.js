@@ -1,6 +1,10 @@
-import { invoke } from '@tauri-apps/api/core'
-import { listen } from '@tauri-apps/api/event'
+import { convertFileSrc, invoke } from '@tauri-apps/api/core'
+import { getCurrentWebviewWindow } from '@tauri-apps/api/webviewWindow'
 
+// Failed commands reject with `{ code, path, message }`. `code` is one of
+// notFound, alreadyExists, permissionDenied, invalidName, invalidPath,
+// conflict, invalidData, invalidQuery, unsupportedImage, tooLarge, unavailable,
+// io or other, and `message` can be shown to the user as is.
 class TauriAPI {
   constructor() {
     this.baseURL = '' // Not needed for Tauri
@@ -9,16 +13,43 @@ class TauriAPI {
 
   async initFileWatcher() {
     try {
-      await invoke('start_file_watcher')
-      await listen('files-changed', () => {
-        // Emit custom event that your frontend can listen to
-        window.dispatchEvent(new CustomEvent('filesChanged'))
+      // Changes are sent to the windows watching the board they happened in,
+      // which a global listener would not tell apart
+      await getCurrentWebviewWindow().listen('files-changed', (event) => {
+        // Emit custom event that your frontend can listen to, carrying the list of changes
+        window.dispatchEvent(new CustomEvent('filesChanged', { detail: event.payload }))
       })
     } catch (error) {
       console.error('Failed to initialize file watcher:', error)
     }
   }
 
+  // Watches the board currently opened, replacing the watcher of the previous one
+  async watchBoard(board) {
+    try {
+      await invoke('start_file_watcher', { board })
+    } catch (error) {
+      console.error('Failed to start file watcher:', error)
+    }
+  }
+
+  async stopFileWatcher() {
+    try {
+      await invoke('stop_file_watcher')
+    } catch (error) {
+      console.error('Failed to stop file watcher:', error)
+    }
+  }
+
+  async getFileWatcherStatus() {
+    try {
+      return await invoke('get_file_watcher_status')
+    } catch (error) {
+      console.error('Error getting file watcher status:', error)
+      return { watching: false, board: null, boards: [] }
+    }
+  }
+
   // Tags API
   async getTags(path) {
     try {
@@ -58,12 +89,49 @@ class TauriAPI {
     }
   }
 
-  async createResource(path, isFile = false, content = '') {
+  // Tree of every board, `{ path, name, lanes, cardCount, overdueCount, lastUpdated, boards, warnings }`,
+  // starting at the root board. `warnings` lists the cards of the board that
+  // could not be read and are left out of the counts.
+  async getBoards() {
+    try {
+      return await invoke('get_boards')
+    } catch (error) {
+      console.error('Error getting boards:', error)
+      throw error
+    }
+  }
+
+  async getCard(path) {
+    try {
+      return await invoke('get_card', { path })
+    } catch (error) {
+      console.error('Error getting card:', error)
+      return null
+    }
+  }
+
+  // `query` is e.g. `tag:bug AND due<2026-11-01 AND lane:"In Progress"`, `sort`
+  // one of name, tags, due, lastUpdated or createdFirst. Returns the matching
+  // cards with their `lane` and `path`.
+  async queryCards(path, query, sort = null, direction = 'asc', limit = null) {
+    try {
+      return await invoke('query_cards', { path, query, sort, direction, limit })
+    } catch (error) {
+      console.error('Error querying cards:', error)
+      throw error
+    }
+  }
+
+  // Resolves to the created path. With onConflict 'fail' a taken name rejects
+  // with an `alreadyExists` error, with 'suffix' the card or lane is created
+  // as "Name (2)" instead.
+  async createResource(path, isFile = false, content = '', onConflict = 'fail') {
     try {
-      await invoke('create_resource', {
+      return await invoke('create_resource', {
         path,
         isFile: isFile ? true : null,
-        content: content || null
+        content: content || null,
+        onConflict
       })
     } catch (error) {
       console.error('Error creating resource:', error)
@@ -71,12 +139,22 @@ class TauriAPI {
     }
   }
 
-  async updateResource(path, newPath = null, content = null) {
+  // Resolves to `{ path, card }`: where the resource ended up and, when its
+  // content was written, the updated card with its new version and parsed
+  // tags and due date. Passing the version the card was loaded with makes the
+  // update fail with a `conflict` error if the file changed on disk in the
+  // meantime. Renaming onto a taken name follows onConflict, as in createResource.
+  // Due dates such as "tomorrow" are saved as YYYY-MM-DD, so they keep
+  // pointing at the same day, unless normalizeDue is false.
+  async updateResource(path, newPath = null, content = null, expectedVersion = null, onConflict = 'fail', normalizeDue = true) {
     try {
-      await invoke('update_resource', {
+      return await invoke('update_resource', {
         path,
         newPath: newPath || null,
-        content: content || null
+        content: content || null,
+        expectedVersion: expectedVersion || null,
+        onConflict,
+        normalizeDue
       })
     } catch (error) {
       console.error('Error updating resource:', error)
@@ -84,6 +162,17 @@ class TauriAPI {
     }
   }
 
+  // Sets one frontmatter field of a card, removing it when value is null, and
+  // resolves to the updated card. Due dates are saved as in updateResource.
+  async updateCardField(path, field, value, expectedVersion = null, normalizeDue = true) {
+    try {
+      return await invoke('update_card_field', { path, field, value, expectedVersion, normalizeDue })
+    } catch (error) {
+      console.error('Error updating card field:', error)
+      throw error
+    }
+  }
+
   async deleteResource(path) {
     try {
       await invoke('delete_resource', { path })
@@ -93,16 +182,42 @@ class TauriAPI {
     }
   }
 
+  // Trash API
+  async listTrash() {
+    try {
+      return await invoke('list_trash')
+    } catch (error) {
+      console.error('Error listing trash:', error)
+      return []
+    }
+  }
+
+  async restoreTrash(id) {
+    try {
+      return await invoke('restore_trash', { id })
+    } catch (error) {
+      console.error('Error restoring from trash:', error)
+      throw error
+    }
+  }
+
+  async purgeTrash(id = null) {
+    try {
+      await invoke('purge_trash', { id })
+    } catch (error) {
+      console.error('Error purging trash:', error)
+      throw error
+    }
+  }
+
   // Image API
   async uploadImage(file) {
     try {
-      // Convert file to array buffer, then to array
-      const arrayBuffer = await file.arrayBuffer()
-      const fileData = Array.from(new Uint8Array(arrayBuffer))
+      // Sent as raw bytes rather than a JSON array, which is several times larger
+      const fileData = new Uint8Array(await file.arrayBuffer())
 
-      return await invoke('upload_image', {
-        fileData,
-        filename: file.name
+      return await invoke('upload_image', fileData, {
+        headers: { 'X-Filename': encodeURIComponent(file.name) }
       })
     } catch (error) {
       console.error('Error uploading image:', error)
@@ -110,14 +225,36 @@ class TauriAPI {
     }
   }
 
+  // URL the webview loads the image from, served by the image URI scheme. It
+  // differs between platforms, so cards store `/_api/image/<name>` instead.
   async getImage(filename) {
+    return convertFileSrc(filename, 'tasksmd-image')
+  }
+
+  // URL of a downscaled preview whose longest side is about `size` pixels,
+  // rounded to one of the configured thumbnail sizes
+  async getThumbnail(filename, size = 256) {
+    return `${convertFileSrc(filename, 'tasksmd-image')}?size=${size}`
+  }
+
+  // Resolves to `{ images: [{ name, size }], reclaimedBytes, unreadableCards }`,
+  // the images no card refers to. They are only listed with dryRun, or when
+  // some cards could not be read, and removed otherwise.
+  async cleanImages(dryRun = true) {
+    try {
+      return await invoke('clean_images', { dryRun })
+    } catch (error) {
+      console.error('Error cleaning up images:', error)
+      throw error
+    }
+  }
+
+  // Renames images uploaded with random names after their content
+  async migrateImages() {
     try {
-      const imageData = await invoke('get_image', { filename })
-      // Convert array back to blob for frontend use
-      const blob = new Blob([new Uint8Array(imageData)])
-      return URL.createObjectURL(blob)
+      return await invoke('migrate_images')
     } catch (error) {
-      console.error('Error getting image:', error)
+      console.error('Error migrating images:', error)
       throw error
     }
   }
@@ -141,6 +278,132 @@ class TauriAPI {
     }
   }
 
+  // Lanes of a board whose cards are finished, left out of the agenda
+  async getDoneLanes(path) {
+    try {
+      return await invoke('get_done_lanes', { path })
+    } catch (error) {
+      console.error('Error getting done lanes:', error)
+      return []
+    }
+  }
+
+  async updateDoneLanes(path, lanes) {
+    try {
+      await invoke('update_done_lanes', { path, lanes })
+    } catch (error) {
+      console.error('Error updating done lanes:', error)
+      throw error
+    }
+  }
+
+  // Resolves to `{ overdue, today, thisWeek, later, warnings }`, cards with a
+  // due date across every board along with their `board`, `lane` and `path`,
+  // and the cards left out as they could not be read
+  async getAgenda(excludeDone = true) {
+    try {
+      return await invoke('get_agenda', { excludeDone })
+    } catch (error) {
+      console.error('Error getting agenda:', error)
+      throw error
+    }
+  }
+
+  // Saved views API. A view is `{ name, query, sort, direction, limit }`,
+  // query and sort as in queryCards
+  async listViews(path) {
+    try {
+      return await invoke('list_views', { path })
+    } catch (error) {
+      console.error('Error listing views:', error)
+      return []
+    }
+  }
+
+  async createView(path, view) {
+    try {
+      await invoke('create_view', { path, view })
+    } catch (error) {
+      console.error('Error creating view:', error)
+      throw error
+    }
+  }
+
+  async updateView(path, name, view) {
+    try {
+      await invoke('update_view', { path, name, view })
+    } catch (error) {
+      console.error('Error updating view:', error)
+      throw error
+    }
+  }
+
+  async deleteView(path, name) {
+    try {
+      await invoke('delete_view', { path, name })
+    } catch (error) {
+      console.error('Error deleting view:', error)
+      throw error
+    }
+  }
+
+  // History API, both resolve to false when there is nothing to undo or redo
+  async undo() {
+    try {
+      return await invoke('undo')
+    } catch (error) {
+      console.error('Error undoing:', error)
+      throw error
+    }
+  }
+
+  async redo() {
+    try {
+      return await invoke('redo')
+    } catch (error) {
+      console.error('Error redoing:', error)
+      throw error
+    }
+  }
+
+  // Git history API, only available when GIT_HISTORY is enabled
+  async getCardHistory(path) {
+    try {
+      return await invoke('get_card_history', { path })
+    } catch (error) {
+      console.error('Error getting card history:', error)
+      throw error
+    }
+  }
+
+  async diffCardVersions(path, from, to = null) {
+    try {
+      return await invoke('diff_card_versions', { path, from, to })
+    } catch (error) {
+      console.error('Error diffing card versions:', error)
+      throw error
+    }
+  }
+
+  async restoreCardVersion(path, commit) {
+    try {
+      return await invoke('restore_card_version', { path, commit })
+    } catch (error) {
+      console.error('Error restoring card version:', error)
+      throw error
+    }
+  }
+
+  // Returns [{ path, board, lane, card, score, snippet: [{ text, highlight }] }], best first
+  async search(query, board = null, limit = null) {
+    try {
+      return await invoke('search', { query, board, limit })
+    } catch (error) {
+      console.error('Error searching cards:', error)
+      throw error
+    }
+  }
+
   // Compatibility methods for existing frontend code
   async get(endpoint) {
     const cleanEndpoint = endpoint.replace(/^\/+|\/+$/g, '')
@@ -1,5 +1,6 @@
-import { createMemo } from 'solid-js'
-import { handleKeyDown } from '../utils'
+import { createMemo, createResource, Show } from 'solid-js'
+import api from '../api'
+import { firstImageName, handleKeyDown } from '../utils'
 
 /**
  *
@@ -7,7 +8,8 @@ import { handleKeyDown } from '../utils'
  * @param {string} props.name
  * @param {boolean} props.disableDrag
  * @param {Object[]} props.tags
- * @param {string} props.dueDate
+ * @param {string} props.dueDate Normalized by the backend as YYYY-MM-DD, possibly followed by a time
+ * @param {string} props.content
  * @param {Function} props.onClick
  * @param {JSX.Element} props.headerSlot
  */
@@ -16,7 +18,7 @@ export function Card(props) {
     if (!props.dueDate) {
       return ''
     }
-    const [year, month, day] = props.dueDate.split('-')
+    const [year, month, day] = props.dueDate.slice(0, 10).split('-')
     const dueDateLocalTime = new Date(year, month - 1, day)
     const dueDateLocalTimeISO = dueDateLocalTime.toISOString().split('T')[0]
     const todayISO = new Date().toISOString().split('T')[0]
@@ -33,11 +35,17 @@ export function Card(props) {
     if (!props.dueDate) {
       return ''
     }
-    const [year, month, day] = props.dueDate.split('-')
+    const [year, month, day] = props.dueDate.slice(0, 10).split('-')
     const dueDateLocalTime = new Date(year, month - 1, day)
     return `Due ${dueDateLocalTime.toLocaleDateString()}`
   })
 
+  // The board only shows a small preview of the first image, never the original
+  const [preview] = createResource(
+    () => firstImageName(props.content),
+    (imageName) => api.getThumbnail(imageName)
+  )
+
   return (
     <div
       role="button"
@@ -70,6 +78,9 @@ export function Card(props) {
         </For>
       </ul>
       <h5 class="card__content">{props.content}</h5>
+      <Show when={preview()}>
+        <img class="card__image" src={preview()} alt="" loading="lazy" draggable="false" />
+      </Show>
       <h5 class={`card__due-date ${dueDateStatusClass()}`}>{dueDateFormatted()}</h5>
     </div>
   )
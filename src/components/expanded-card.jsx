@@ -1,7 +1,7 @@
 import { createEffect, createSignal, onMount, createMemo, onCleanup } from 'solid-js'
 import api from '../api'
 import { Menu } from './menu'
-import { handleKeyDown, clickOutside } from '../utils'
+import { handleKeyDown, clickOutside, IMAGE_PATH, imageName } from '../utils'
 import { makePersisted } from '@solid-primitives/storage'
 import { NameInput } from './name-input'
 import { Portal } from 'solid-js/web'
@@ -61,6 +61,7 @@ function ExpandedCard(props) {
   let backdropRef
   let tagsInputRef
   let editorContainerRef
+  let imageObserver
 
   function handleTagRenameChange(newValue) {
     setNewTagName(newValue)
@@ -146,16 +147,19 @@ function ExpandedCard(props) {
     setIsCardBeingRenamed(true)
   }
 
+  // Cards keep the portable `/_api/image/<name>` form of the web version,
+  // which only points at the image URI scheme where it is shown
   function uploadImage(file) {
-    const formData = new FormData()
-    formData.set('file', file)
-    return api
-      .uploadImage(formData)
-      .then((res) => res.text())
-      .then((imageName) => {
-        handleEditorOnChange()
-        return `${api}/image/${imageName}`
-      })
+    return api.uploadImage(file).then((name) => {
+      handleEditorOnChange()
+      return `${IMAGE_PATH}${name}`
+    })
+  }
+
+  async function resolveImages() {
+    for (const image of editorContainerRef.querySelectorAll(`img[src*="${IMAGE_PATH}"]`)) {
+      image.src = await api.getImage(imageName(image.getAttribute('src')))
+    }
   }
 
   function handleEditorOnChange(e) {
@@ -260,6 +264,11 @@ function ExpandedCard(props) {
       imageUpload: { handler: uploadImage }
     })
     setEditor(newEditor)
+    // Images are atoms to the editor, so their rendered source can change
+    // without touching the card content
+    imageObserver = new MutationObserver(resolveImages)
+    imageObserver.observe(editorContainerRef, { subtree: true, childList: true, attributes: true, attributeFilter: ['src'] })
+    resolveImages()
     const toolbarEndGroupNodes = [
       ...editorContainerRef.childNodes[0].childNodes[1].childNodes[0].childNodes[1].childNodes[0].childNodes
     ]
@@ -289,6 +298,7 @@ function ExpandedCard(props) {
     for (const btn of modeBtns()) {
       btn.removeEventListener('click', handleClickEditorMode)
     }
+    imageObserver?.disconnect()
   })
 
   function handleDialogCancel(e) {
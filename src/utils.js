@@ -1,5 +1,25 @@
 import { onCleanup } from "solid-js";
 
+// Cards refer to uploaded images as `/_api/image/<name>`, the portable form
+// of the web version, possibly preceded by its host
+export const IMAGE_PATH = "/_api/image/";
+
+export function imageName(src) {
+	return decodeURIComponent(
+		src.substring(src.lastIndexOf(IMAGE_PATH) + IMAGE_PATH.length),
+	);
+}
+
+// Name of the first uploaded image a card's content refers to
+export function firstImageName(content) {
+	const match = content?.match(/\/_api\/image\/([^)\s"'>]+)/);
+	try {
+		return match ? decodeURIComponent(match[1]) : null;
+	} catch {
+		return null;
+	}
+}
+
 export function clickOutside(el, accessor) {
 	const onClick = (e) => !el.contains(e.target) && accessor()?.();
 	document.body.addEventListener("click", onClick);